@@ -0,0 +1,9 @@
+CREATE TABLE failed_deliveries (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    n_attempts SMALLINT NOT NULL,
+    last_error TEXT NOT NULL,
+    failed_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
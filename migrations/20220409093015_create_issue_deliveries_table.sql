@@ -0,0 +1,18 @@
+CREATE TABLE issue_deliveries (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    status TEXT NOT NULL,
+    updated_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
+
+-- Backfill the status of deliveries that are still in flight
+INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_email, status, updated_at)
+SELECT newsletter_issue_id, subscriber_email, 'queued', now()
+FROM issue_delivery_queue;
+
+INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_email, status, updated_at)
+SELECT newsletter_issue_id, subscriber_email, 'failed', failed_at
+FROM failed_deliveries
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,5 @@
+-- `published_at` is the time an issue goes out: for scheduled issues it lies in the future
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NOT NULL DEFAULT 'published';
+CREATE INDEX newsletter_issues_scheduled_idx
+    ON newsletter_issues (published_at)
+    WHERE status = 'scheduled';
@@ -0,0 +1,14 @@
+CREATE TABLE newsletter_drafts (
+    draft_id uuid NOT NULL,
+    user_id uuid NOT NULL
+        REFERENCES users (user_id),
+    title TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    updated_at timestamptz NOT NULL,
+    -- Set once the draft has been published, a draft can only go out once
+    published_issue_id uuid NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    PRIMARY KEY(draft_id)
+);
@@ -0,0 +1,12 @@
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+ALTER TABLE newsletter_issues
+    ADD COLUMN hidden_from_archive BOOLEAN NOT NULL DEFAULT false;
+-- Issues published so far get a slug made unique by a prefix of their id
+UPDATE newsletter_issues
+SET slug = concat_ws(
+    '-',
+    NULLIF(trim(BOTH '-' FROM regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g')), ''),
+    left(newsletter_issue_id::text, 8)
+);
+ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
@@ -0,0 +1,2 @@
+-- When set, `html_content` and `text_content` are rendered from it on save
+ALTER TABLE newsletter_drafts ADD COLUMN markdown_content TEXT NULL;
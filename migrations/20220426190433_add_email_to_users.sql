@@ -0,0 +1,2 @@
+-- Where test sends of a newsletter go by default
+ALTER TABLE users ADD COLUMN email TEXT NULL;
@@ -0,0 +1,11 @@
+-- Bounces and spam complaints reported by the email provider.
+-- `provider_event_id` makes the webhook idempotent: providers retry deliveries.
+CREATE TABLE email_events (
+    provider_event_id BIGINT NOT NULL,
+    record_type TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    subscriber_email TEXT NOT NULL,
+    description TEXT NULL,
+    received_at timestamptz NOT NULL,
+    PRIMARY KEY(record_type, provider_event_id)
+);
@@ -0,0 +1,7 @@
+ALTER TABLE newsletter_issues ADD COLUMN track_opens BOOLEAN NOT NULL DEFAULT false;
+-- `tracking_token` is random: tracking URLs must not reveal who opened an issue
+ALTER TABLE issue_deliveries ADD COLUMN tracking_token TEXT NULL;
+ALTER TABLE issue_deliveries ADD COLUMN first_opened_at timestamptz NULL;
+ALTER TABLE issue_deliveries ADD COLUMN n_opens INTEGER NOT NULL DEFAULT 0;
+CREATE UNIQUE INDEX issue_deliveries_tracking_token_idx
+    ON issue_deliveries (tracking_token);
@@ -0,0 +1,20 @@
+ALTER TABLE newsletter_issues ADD COLUMN track_clicks BOOLEAN NOT NULL DEFAULT false;
+-- Tracked links redirect to the URLs stored here, never to a URL taken from
+-- the request: `/t/c/` cannot be used as an open redirect.
+CREATE TABLE issue_links (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    link_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, link_id)
+);
+CREATE TABLE link_clicks (
+    newsletter_issue_id uuid NOT NULL,
+    link_id INTEGER NOT NULL,
+    subscriber_email TEXT NOT NULL,
+    first_clicked_at timestamptz NOT NULL,
+    n_clicks INTEGER NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, link_id, subscriber_email),
+    FOREIGN KEY(newsletter_issue_id, link_id)
+        REFERENCES issue_links (newsletter_issue_id, link_id)
+);
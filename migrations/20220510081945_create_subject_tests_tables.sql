@@ -0,0 +1,25 @@
+-- An issue testing subject lines first goes to a random sample of the list,
+-- each subject line to an equal share of it. Once the test is over, the rest
+-- of the list gets the subject line with the best open rate.
+CREATE TABLE subject_tests (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    sample_percent SMALLINT NOT NULL,
+    test_duration_hours INTEGER NOT NULL,
+    -- Set when the sample is queued for delivery
+    test_ends_at timestamptz NULL,
+    -- Set when the rest of the list is queued for delivery
+    winning_variant_id SMALLINT NULL,
+    PRIMARY KEY(newsletter_issue_id)
+);
+CREATE INDEX subject_tests_due_idx
+    ON subject_tests (test_ends_at)
+    WHERE winning_variant_id IS NULL;
+CREATE TABLE subject_variants (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES subject_tests (newsletter_issue_id),
+    variant_id SMALLINT NOT NULL,
+    subject TEXT NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, variant_id)
+);
+ALTER TABLE issue_deliveries ADD COLUMN variant_id SMALLINT NULL;
@@ -0,0 +1,36 @@
+-- Subscribers join lists one by one: the status of a membership says whether
+-- the subscriber gets the issues of that list, while `subscriptions.status`
+-- is about the address itself (confirmed, bounced, ...).
+CREATE TABLE lists (
+    list_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    -- Subscriptions and issues that do not name a list go to the default list
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(list_id)
+);
+CREATE UNIQUE INDEX lists_default_idx ON lists (is_default) WHERE is_default;
+CREATE TABLE list_memberships (
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    PRIMARY KEY(list_id, subscriber_id)
+);
+-- Everybody was on the one implicit list so far
+INSERT INTO lists (list_id, name, is_default, created_at)
+VALUES ('5c1a7e8e-3b4f-4c61-9a0d-2f6e8b7d1c90', 'Newsletter', true, now());
+INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+SELECT
+    l.list_id,
+    s.id,
+    CASE s.status
+        WHEN 'pending_confirmation' THEN 'pending_confirmation'
+        WHEN 'unsubscribed' THEN 'unsubscribed'
+        ELSE 'confirmed'
+    END,
+    s.subscribed_at
+FROM subscriptions s, lists l;
+ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE newsletter_issues SET list_id = (SELECT list_id FROM lists);
+ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
@@ -0,0 +1,38 @@
+-- Tags are free-form labels attached to subscribers, used to narrow down who
+-- an issue goes to.
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    tag TEXT NOT NULL,
+    PRIMARY KEY(subscriber_id, tag)
+);
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+-- An issue goes to the members of its list with at least one of
+-- `include_tags` (everybody if empty) and none of `exclude_tags`
+ALTER TABLE newsletter_issues ADD COLUMN include_tags TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE newsletter_issues ADD COLUMN exclude_tags TEXT[] NOT NULL DEFAULT '{}';
+-- The recipients of an issue sent to list $1 with include tags $2 and
+-- exclude tags $3. Queuing deliveries and counting recipients ahead of a
+-- send both go through it, so that they always agree.
+CREATE FUNCTION issue_recipients(uuid, TEXT[], TEXT[])
+RETURNS TABLE (subscriber_id uuid, email TEXT)
+LANGUAGE sql STABLE
+AS $$
+    SELECT s.id, s.email
+    FROM subscriptions s
+    JOIN list_memberships m ON m.subscriber_id = s.id
+    WHERE
+        m.list_id = $1 AND
+        m.status = 'confirmed' AND
+        s.status = 'confirmed' AND
+        (
+            cardinality($2) = 0 OR
+            EXISTS (
+                SELECT 1 FROM subscriber_tags t
+                WHERE t.subscriber_id = s.id AND t.tag = ANY($2)
+            )
+        ) AND
+        NOT EXISTS (
+            SELECT 1 FROM subscriber_tags t
+            WHERE t.subscriber_id = s.id AND t.tag = ANY($3)
+        )
+$$;
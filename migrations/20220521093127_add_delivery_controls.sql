@@ -0,0 +1,28 @@
+-- Deliveries of a paused issue stay queued until it is resumed
+ALTER TABLE newsletter_issues ADD COLUMN delivery_paused BOOLEAN NOT NULL DEFAULT false;
+-- Who stopped, restarted or cancelled the delivery of an issue, and when
+CREATE TABLE issue_audit_log (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    action TEXT NOT NULL,
+    user_id uuid NOT NULL
+        REFERENCES users (user_id),
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX issue_audit_log_issue_idx ON issue_audit_log (newsletter_issue_id);
+-- Whether issue $1 still has deliveries to send: queued ones, or the rest of
+-- the list once its subject line test is over.
+CREATE FUNCTION is_being_delivered(uuid)
+RETURNS boolean
+LANGUAGE sql STABLE
+AS $$
+    SELECT
+        EXISTS (
+            SELECT 1 FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = $1
+        ) OR
+        EXISTS (
+            SELECT 1 FROM subject_tests t
+            WHERE t.newsletter_issue_id = $1 AND t.winning_variant_id IS NULL
+        )
+$$;
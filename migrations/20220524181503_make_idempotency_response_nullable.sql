@@ -0,0 +1,5 @@
+-- Requests claim their idempotency key before they are processed: the
+-- response is only known once they are done.
+ALTER TABLE idempotency ALTER COLUMN response_status_code DROP NOT NULL;
+ALTER TABLE idempotency ALTER COLUMN response_body DROP NOT NULL;
+ALTER TABLE idempotency ALTER COLUMN response_headers DROP NOT NULL;
@@ -0,0 +1,24 @@
+-- The issue an email event is about: the last one sent to the address before
+-- the event was received. Providers do not tell us.
+ALTER TABLE email_events ADD COLUMN newsletter_issue_id uuid NULL
+    REFERENCES newsletter_issues (newsletter_issue_id);
+UPDATE email_events e
+SET newsletter_issue_id = (
+    SELECT d.newsletter_issue_id
+    FROM issue_deliveries d
+    WHERE
+        d.subscriber_email = e.subscriber_email AND
+        d.status = 'sent' AND
+        d.updated_at <= e.received_at
+    ORDER BY d.updated_at DESC
+    LIMIT 1
+);
+CREATE INDEX email_events_issue_idx ON email_events (newsletter_issue_id);
+-- Subscribers who left through the unsubscribe link of an issue
+CREATE TABLE issue_unsubscribes (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    unsubscribed_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
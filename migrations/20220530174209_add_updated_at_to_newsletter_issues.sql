@@ -0,0 +1,3 @@
+-- When the issue last changed in a way that shows in the archive and feeds
+ALTER TABLE newsletter_issues ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
+UPDATE newsletter_issues SET updated_at = published_at WHERE status <> 'scheduled';
@@ -0,0 +1,3 @@
+-- The claim of the worker busy with the delivery: once it runs out and
+-- another worker claims the delivery, the first one must not send it.
+ALTER TABLE issue_delivery_queue ADD COLUMN claim_token uuid;
@@ -0,0 +1,25 @@
+#!/usr/bin/env bash
+set -x
+set -eo pipefail
+
+# if an SMTP sink container is running, print instructions to kill it and exit
+RUNNING_CONTAINER=$(docker ps --filter 'name=mailhog' --format '{{.ID}}')
+if [[ -n $RUNNING_CONTAINER ]]; then
+  echo >&2 "there is a mailhog container already running, kill it with"
+  echo >&2 "  docker kill ${RUNNING_CONTAINER}"
+  exit 1
+fi
+
+# Launch MailHog using Docker: it accepts any message on port 1025 and
+# shows them at http://localhost:8025
+docker run \
+  -p "1025:1025" \
+  -p "8025:8025" \
+  -d \
+  --name "mailhog_$(date '+%s')" \
+  mailhog/mailhog
+
+>&2 echo "MailHog is ready to go! Run the app with"
+>&2 echo "  APP_EMAIL_CLIENT__BACKEND=smtp APP_EMAIL_CLIENT__SMTP__HOST=localhost \\"
+>&2 echo "  APP_EMAIL_CLIENT__SMTP__PORT=1025 APP_EMAIL_CLIENT__SMTP__TLS=none \\"
+>&2 echo "  APP_EMAIL_CLIENT__SMTP__MAX_CONNECTIONS=4 cargo run"
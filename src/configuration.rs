@@ -1,15 +1,20 @@
 use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    EmailTransport, Outbox, OutboxClient, PostmarkClient, SmtpClient, ThrottleSettings,
+};
 use secrecy::Secret;
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
 use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub email_events_webhook: WebhookSettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -31,14 +36,82 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// Set from `APP_ENVIRONMENT` by `get_configuration`.
+    pub environment: Environment,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    /// Which service delivers our emails.
+    #[serde(default)]
+    pub backend: EmailBackend,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: String,
     pub timeout_milliseconds: u64,
+    /// The sending rate allowed by the provider.
+    pub max_messages_per_second: u32,
+    pub max_concurrent_requests: usize,
+    /// Only used by the `smtp` backend.
+    #[serde(default)]
+    pub smtp: Option<SmtpSettings>,
+    /// Only used by the `outbox` backend.
+    #[serde(default)]
+    pub outbox: Option<OutboxSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailBackend {
+    Postmark,
+    Smtp,
+    /// Write emails to a directory instead of sending them, for local
+    /// development.
+    Outbox,
+}
+
+impl Default for EmailBackend {
+    fn default() -> Self {
+        EmailBackend::Postmark
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    /// Messages are sent without authenticating unless both are set.
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    /// How many connections to the relay are kept open and reused.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_connections: u32,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain text, for relays on the local network only.
+    None,
+    /// Upgrade the connection with `STARTTLS`, usually on port 587.
+    StartTls,
+    /// TLS from the first byte, usually on port 465.
+    Implicit,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct OutboxSettings {
+    pub directory: String,
+}
+
+/// The basic auth credentials the email provider must present when it
+/// reports bounces and spam complaints.
+#[derive(serde::Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
 }
 
 impl DatabaseSettings {
@@ -65,6 +138,46 @@ impl DatabaseSettings {
 }
 
 impl EmailClientSettings {
+    pub fn client(self) -> Arc<dyn EmailTransport> {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        let throttle = self.throttle();
+        match self.backend {
+            EmailBackend::Postmark => Arc::new(PostmarkClient::new(
+                self.base_url,
+                sender_email,
+                self.authorization_token,
+                timeout,
+                throttle,
+            )),
+            EmailBackend::Smtp => {
+                let smtp = self
+                    .smtp
+                    .expect("The `smtp` backend needs `email_client.smtp` settings.");
+                Arc::new(
+                    SmtpClient::new(sender_email, &smtp, timeout).expect("Invalid SMTP settings."),
+                )
+            }
+            EmailBackend::Outbox => {
+                let outbox = self
+                    .outbox()
+                    .expect("The `outbox` backend needs `email_client.outbox` settings.");
+                Arc::new(OutboxClient::new(sender_email, outbox))
+            }
+        }
+    }
+
+    /// Where emails are written to, if they are written to an outbox rather
+    /// than sent.
+    pub fn outbox(&self) -> Option<Outbox> {
+        match (self.backend, &self.outbox) {
+            (EmailBackend::Outbox, Some(outbox)) => {
+                Some(Outbox::new(outbox.directory.clone().into()))
+            }
+            _ => None,
+        }
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
@@ -72,8 +185,17 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
+
+    pub fn throttle(&self) -> ThrottleSettings {
+        ThrottleSettings {
+            max_messages_per_second: self.max_messages_per_second,
+            max_concurrent_requests: self.max_concurrent_requests,
+        }
+    }
 }
 
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "String")]
 pub enum Environment {
     Local,
     Production,
@@ -125,6 +247,7 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     // Add settings from environment vars prefixed with APP and separator __
     // E.g. `APP_APPLICATION__PORT=5001` would set `Settings.application.port=5001`
     settings.merge(config::Environment::with_prefix("app").separator("__"))?;
+    settings.set("application.environment", environment.as_str())?;
 
     // Try to convert read config file into Setting type
     settings.try_into()
@@ -0,0 +1,69 @@
+use std::convert::TryFrom;
+
+/// Where the delivery of a newsletter issue to a single subscriber stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Queued,
+    /// Handed over to the email provider, the outcome is not known yet.
+    Sending,
+    Sent,
+    Failed,
+    /// The stored email address of the subscriber is not valid.
+    Skipped,
+    /// The issue was cancelled before it reached the subscriber.
+    Cancelled,
+}
+
+impl DeliveryStatus {
+    pub const ALL: [DeliveryStatus; 6] = [
+        DeliveryStatus::Queued,
+        DeliveryStatus::Sending,
+        DeliveryStatus::Sent,
+        DeliveryStatus::Failed,
+        DeliveryStatus::Skipped,
+        DeliveryStatus::Cancelled,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Queued => "queued",
+            DeliveryStatus::Sending => "sending",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Skipped => "skipped",
+            DeliveryStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryStatus {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        DeliveryStatus::ALL
+            .iter()
+            .find(|status| status.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("{} is not a valid delivery status.", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryStatus;
+    use claim::assert_err;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn every_status_round_trips_through_its_string_representation() {
+        for status in DeliveryStatus::ALL {
+            let parsed = DeliveryStatus::try_from(status.as_str().to_string()).unwrap();
+            assert_eq!(parsed, status);
+        }
+    }
+
+    #[test]
+    fn unknown_statuses_are_rejected() {
+        assert_err!(DeliveryStatus::try_from("delivered".to_string()));
+    }
+}
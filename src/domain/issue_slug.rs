@@ -0,0 +1,71 @@
+/// The URL-friendly identifier of a newsletter issue in the public archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssueSlug(String);
+
+impl IssueSlug {
+    /// Derive a slug from an issue title: runs of anything but ASCII letters and
+    /// digits become a single dash. Titles without any usable character fall
+    /// back to `issue`.
+    pub fn from_title(title: &str) -> IssueSlug {
+        let mut slug = String::with_capacity(title.len());
+        for c in title.chars() {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c.to_ascii_lowercase());
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+        let slug = slug.trim_end_matches('-');
+        if slug.is_empty() {
+            IssueSlug("issue".into())
+        } else {
+            IssueSlug(slug.into())
+        }
+    }
+
+    /// Used to tell apart issues sharing the same title: `title`, `title-2`, ...
+    pub fn with_suffix(&self, n: u32) -> IssueSlug {
+        IssueSlug(format!("{}-{}", self.0, n))
+    }
+}
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for IssueSlug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IssueSlug;
+
+    #[test]
+    fn titles_are_lowercased_and_dashed() {
+        let slug = IssueSlug::from_title("Our  April Issue: What's new?");
+        assert_eq!(slug.as_ref(), "our-april-issue-what-s-new");
+    }
+
+    #[test]
+    fn titles_without_usable_characters_fall_back_to_a_default() {
+        assert_eq!(IssueSlug::from_title("").as_ref(), "issue");
+        assert_eq!(IssueSlug::from_title("¿¡!?").as_ref(), "issue");
+    }
+
+    #[test]
+    fn non_ascii_characters_are_dropped() {
+        let slug = IssueSlug::from_title("Ça va, été 2022");
+        assert_eq!(slug.as_ref(), "a-va-t-2022");
+    }
+
+    #[test]
+    fn suffixes_are_appended_with_a_dash() {
+        let slug = IssueSlug::from_title("Weekly digest").with_suffix(2);
+        assert_eq!(slug.as_ref(), "weekly-digest-2");
+    }
+}
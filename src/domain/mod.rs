@@ -1,7 +1,13 @@
+mod delivery_status;
+mod issue_slug;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tags;
 
+pub use delivery_status::DeliveryStatus;
+pub use issue_slug::IssueSlug;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tags::SubscriberTags;
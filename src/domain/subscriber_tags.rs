@@ -0,0 +1,70 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A set of subscriber tags, as entered in forms: comma-separated, in any
+/// case. Tags are stored lowercase, so that `VIP` and `vip` are the same tag.
+#[derive(Debug, Default, PartialEq)]
+pub struct SubscriberTags(Vec<String>);
+
+impl SubscriberTags {
+    pub fn parse(s: &str) -> Result<SubscriberTags, String> {
+        let forbidden_chars = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
+        let mut tags = Vec::new();
+        for tag in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            if tag.graphemes(true).count() > 64
+                || tag
+                    .chars()
+                    .any(|c| c.is_control() || forbidden_chars.contains(&c))
+            {
+                return Err(format!("{} is not a valid tag.", tag));
+            }
+            tags.push(tag.to_lowercase());
+        }
+        tags.sort();
+        tags.dedup();
+        Ok(Self(tags))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[String] {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for SubscriberTags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.join(", ").fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTags;
+    use claim::assert_err;
+
+    #[test]
+    fn tags_are_comma_separated_and_case_insensitive() {
+        let tags = SubscriberTags::parse(" VIP, beta-testers,,vip ").unwrap();
+        assert_eq!(tags.as_slice(), ["beta-testers", "vip"]);
+        assert_eq!(tags.to_string(), "beta-testers, vip");
+    }
+
+    #[test]
+    fn blank_input_means_no_tags() {
+        assert!(SubscriberTags::parse(" , ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_tag_longer_than_64_graphemes_is_invalid() {
+        assert_err!(SubscriberTags::parse(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn tags_containing_an_invalid_character_are_invalid() {
+        for tag in ['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
+            assert_err!(SubscriberTags::parse(&format!("tag{}", tag)));
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Sending emails, whatever the service that actually delivers them.
+//!
+//! Routes and the delivery worker only know about [`EmailTransport`]: the
+//! implementation is picked by the `backend` field of
+//! [`EmailClientSettings`](crate::configuration::EmailClientSettings).
+mod outbox;
+mod postmark;
+mod smtp;
+
+use crate::domain::SubscriberEmail;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart, SinglePart};
+use lettre::Message;
+
+pub use outbox::{Outbox, OutboxClient, OutboxMessage};
+pub use postmark::{PostmarkClient, ThrottleSettings};
+pub use smtp::SmtpClient;
+
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), SendError>;
+
+    /// Send many emails with as few requests as possible, returning the
+    /// outcome of each message in the order they were given.
+    ///
+    /// Transports without a batch API send the messages one at a time.
+    async fn send_email_batch(&self, emails: &[Email<'_>]) -> Vec<Result<(), SendError>> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for email in emails {
+            let outcome = self
+                .send_email(
+                    email.recipient,
+                    email.subject,
+                    email.html_content,
+                    email.text_content,
+                    email.headers,
+                )
+                .await;
+            outcomes.push(outcome);
+        }
+        outcomes
+    }
+
+    /// How many messages [`send_email_batch`](Self::send_email_batch) sends
+    /// with a single request.
+    fn max_batch_size(&self) -> usize {
+        1
+    }
+}
+
+/// A single message of a batch.
+pub struct Email<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub headers: &'a [EmailHeader],
+}
+
+/// A header to add to a message, on top of the ones every message gets.
+#[derive(Debug, Clone)]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+/// Why a message was not sent.
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum SendError {
+    /// The request carrying the message failed as a whole.
+    #[error("Failed to send the email: {message}")]
+    RequestFailed { message: String, is_transient: bool },
+    /// The provider took the request but not this message, e.g. because
+    /// the recipient has been marked as inactive.
+    #[error("The email provider rejected the message (error code {error_code}): {message}")]
+    Rejected { error_code: i64, message: String },
+}
+
+impl SendError {
+    /// Whether sending the same message again later might succeed.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            SendError::RequestFailed { is_transient, .. } => *is_transient,
+            SendError::Rejected { .. } => false,
+        }
+    }
+}
+
+/// A `multipart/alternative` message with both bodies, the text body first
+/// so that clients able to render HTML pick the HTML body.
+fn mime_message(
+    sender: &Mailbox,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    headers: &[EmailHeader],
+) -> Result<Message, SendError> {
+    let mut builder = Message::builder()
+        .from(sender.clone())
+        .to(recipient.as_ref().parse().map_err(invalid_message)?)
+        .subject(subject);
+    for header in headers {
+        let name = HeaderName::new_from_ascii(header.name.clone()).map_err(invalid_message)?;
+        builder = builder.raw_header(HeaderValue::new(name, header.value.clone()));
+    }
+    builder
+        .multipart(
+            MultiPart::alternative()
+                .singlepart(SinglePart::plain(text_content.to_string()))
+                .singlepart(SinglePart::html(html_content.to_string())),
+        )
+        .map_err(invalid_message)
+}
+
+/// Messages that cannot be built are not going to get any better.
+fn invalid_message(e: impl std::fmt::Display) -> SendError {
+    SendError::RequestFailed {
+        message: format!("Failed to build the message: {}", e),
+        is_transient: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Email, EmailHeader, EmailTransport, SendError};
+    use crate::domain::SubscriberEmail;
+    use std::sync::Mutex;
+
+    /// Records every recipient, and refuses to send to `bounce@example.com`.
+    #[derive(Default)]
+    struct RecordingTransport(Mutex<Vec<String>>);
+
+    #[async_trait::async_trait]
+    impl EmailTransport for RecordingTransport {
+        async fn send_email(
+            &self,
+            recipient: &SubscriberEmail,
+            _subject: &str,
+            _html_content: &str,
+            _text_content: &str,
+            _headers: &[EmailHeader],
+        ) -> Result<(), SendError> {
+            self.0.lock().unwrap().push(recipient.as_ref().to_string());
+            match recipient.as_ref() {
+                "bounce@example.com" => Err(SendError::Rejected {
+                    error_code: 550,
+                    message: "No such user.".into(),
+                }),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn batches_are_sent_one_message_at_a_time_by_default() {
+        // Arrange
+        let transport = RecordingTransport::default();
+        let recipients: Vec<SubscriberEmail> =
+            ["a@example.com", "bounce@example.com", "b@example.com"]
+                .iter()
+                .map(|e| SubscriberEmail::parse(e.to_string()).unwrap())
+                .collect();
+        let emails: Vec<Email> = recipients
+            .iter()
+            .map(|recipient| Email {
+                recipient,
+                subject: "Subject",
+                html_content: "<p>Body</p>",
+                text_content: "Body",
+                headers: &[],
+            })
+            .collect();
+
+        // Act
+        let outcomes = transport.send_email_batch(&emails).await;
+
+        // Assert
+        assert_eq!(
+            *transport.0.lock().unwrap(),
+            vec!["a@example.com", "bounce@example.com", "b@example.com"]
+        );
+        assert!(outcomes[0].is_ok());
+        assert!(matches!(
+            outcomes[1],
+            Err(SendError::Rejected {
+                error_code: 550,
+                ..
+            })
+        ));
+        assert!(outcomes[2].is_ok());
+    }
+}
@@ -0,0 +1,225 @@
+use super::{mime_message, EmailHeader, EmailTransport, SendError};
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use chrono::Utc;
+use lettre::message::Mailbox;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// A directory holding every email "sent" by an [`OutboxClient`]: each
+/// message is stored as an RFC 5322 `.eml` file, next to a JSON sidecar with
+/// the details listed in the admin pages.
+#[derive(Clone, Debug)]
+pub struct Outbox {
+    directory: PathBuf,
+}
+
+/// The JSON sidecar of a message in the outbox.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct OutboxMessage {
+    /// Starts with the time the message was sent, so that ids sort
+    /// chronologically.
+    pub id: String,
+    pub sent_at: String,
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl Outbox {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    async fn store(&self, message: &OutboxMessage, eml: &[u8]) -> Result<(), std::io::Error> {
+        tokio::fs::create_dir_all(&self.directory).await?;
+        // The sidecar goes last: messages are listed from their sidecar, so
+        // a message never shows up without its `.eml` file.
+        tokio::fs::write(self.path(&message.id, "eml"), eml).await?;
+        tokio::fs::write(
+            self.path(&message.id, "json"),
+            serde_json::to_vec_pretty(message)?,
+        )
+        .await
+    }
+
+    /// Every message in the outbox, the most recent first.
+    pub async fn list(&self) -> Result<Vec<OutboxMessage>, anyhow::Error> {
+        let mut entries = match tokio::fs::read_dir(&self.directory).await {
+            Ok(entries) => entries,
+            // Nothing has been sent yet.
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("Failed to read the outbox directory."),
+        };
+        let mut messages = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().map_or(false, |e| e == "json") {
+                let json = tokio::fs::read(&path).await?;
+                let message: OutboxMessage = serde_json::from_slice(&json)
+                    .with_context(|| format!("Failed to parse {}.", path.display()))?;
+                messages.push(message);
+            }
+        }
+        messages.sort_by(|a, b| b.id.cmp(&a.id));
+        Ok(messages)
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<OutboxMessage>, anyhow::Error> {
+        match self.read(id, "json").await? {
+            Some(json) => Ok(Some(serde_json::from_slice(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The message as it would have gone over the wire.
+    pub async fn raw(&self, id: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        self.read(id, "eml").await
+    }
+
+    async fn read(&self, id: &str, extension: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        // Ids come from URLs: anything but the ids we generate could point
+        // outside of the outbox.
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Ok(None);
+        }
+        match tokio::fs::read(self.path(id, extension)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read a message from the outbox."),
+        }
+    }
+
+    fn path(&self, id: &str, extension: &str) -> PathBuf {
+        self.directory.join(format!("{}.{}", id, extension))
+    }
+}
+
+/// Writes emails to an [`Outbox`] instead of sending them, for local
+/// development.
+pub struct OutboxClient {
+    outbox: Outbox,
+    sender: Mailbox,
+}
+
+impl OutboxClient {
+    pub fn new(sender: SubscriberEmail, outbox: Outbox) -> Self {
+        Self {
+            outbox,
+            sender: sender
+                .as_ref()
+                .parse()
+                .expect("The sender address is not a valid mailbox."),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for OutboxClient {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), SendError> {
+        let eml = mime_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )?
+        .formatted();
+        let now = Utc::now();
+        let message = OutboxMessage {
+            id: format!(
+                "{}-{}",
+                now.format("%Y%m%d%H%M%S%6f"),
+                Uuid::new_v4().to_simple()
+            ),
+            sent_at: now.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            from: self.sender.to_string(),
+            to: recipient.as_ref().to_string(),
+            subject: subject.to_string(),
+            html_body: html_content.to_string(),
+            text_body: text_content.to_string(),
+        };
+        self.outbox
+            .store(&message, &eml)
+            .await
+            .map_err(|e| SendError::RequestFailed {
+                message: format!("Failed to write to the outbox: {}", e),
+                is_transient: true,
+            })?;
+        tracing::info!(
+            outbox_message_id = %message.id,
+            "An email has been written to the outbox."
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Outbox, OutboxClient};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::EmailTransport;
+    use claim::{assert_none, assert_some};
+
+    fn outbox() -> Outbox {
+        Outbox::new(std::env::temp_dir().join(uuid::Uuid::new_v4().to_string()))
+    }
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn sent_emails_are_listed_most_recent_first() {
+        // Arrange
+        let outbox = outbox();
+        let client = OutboxClient::new(email("newsletter@example.com"), outbox.clone());
+
+        // Act
+        for subject in ["First", "Second"] {
+            client
+                .send_email(
+                    &email("ursula@example.com"),
+                    subject,
+                    "<p>Hi</p>",
+                    "Hi",
+                    &[],
+                )
+                .await
+                .unwrap();
+        }
+
+        // Assert
+        let messages = outbox.list().await.unwrap();
+        let subjects: Vec<_> = messages.iter().map(|m| m.subject.as_str()).collect();
+        assert_eq!(subjects, vec!["Second", "First"]);
+        let raw = assert_some!(outbox.raw(&messages[0].id).await.unwrap());
+        let raw = String::from_utf8(raw).unwrap();
+        assert!(raw.contains("To: ursula@example.com"));
+        assert!(raw.contains("Subject: Second"));
+        assert!(raw.contains("multipart/alternative"));
+    }
+
+    #[tokio::test]
+    async fn an_outbox_that_was_never_written_to_is_empty() {
+        assert!(outbox().list().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn ids_cannot_point_outside_of_the_outbox() {
+        let outbox = outbox();
+        assert_none!(outbox.get("../../etc/passwd").await.unwrap());
+        assert_none!(outbox.raw("..").await.unwrap());
+    }
+}
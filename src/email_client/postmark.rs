@@ -0,0 +1,800 @@
+use super::{Email, EmailHeader, EmailTransport, SendError};
+use crate::domain::SubscriberEmail;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Response, StatusCode};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+
+/// How many times a message that was rate-limited is sent again before
+/// giving up on it.
+const MAX_RATE_LIMITED_RETRIES: u32 = 3;
+/// We would rather fail (and let the caller retry later) than hold on to a
+/// message for longer than this.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// The most messages Postmark accepts in a single batch request.
+const MAX_BATCH_SIZE: usize = 500;
+
+pub struct PostmarkClient {
+    http_client: Client,
+    base_url: reqwest::Url,
+    sender: SubscriberEmail,
+    authorization_token: String,
+    throttle: Throttle,
+}
+
+/// How fast a client may send, to stay within the limits of the provider.
+#[derive(Clone, Copy, Debug)]
+pub struct ThrottleSettings {
+    pub max_messages_per_second: u32,
+    pub max_concurrent_requests: usize,
+}
+
+impl PostmarkClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        authorization_token: String,
+        timeout: std::time::Duration,
+        throttle: ThrottleSettings,
+    ) -> Self {
+        let base_url = reqwest::Url::parse(&base_url).expect("Invalid base url.");
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            sender,
+            authorization_token,
+            throttle: Throttle::new(throttle),
+        }
+    }
+
+    async fn send_chunk(&self, emails: &[Email<'_>]) -> Vec<Result<(), SendError>> {
+        let request_body: Vec<SendEmailRequest> = emails
+            .iter()
+            .map(|email| SendEmailRequest {
+                from: self.sender.as_ref(),
+                to: email.recipient.as_ref(),
+                subject: email.subject,
+                html_body: email.html_content,
+                text_body: email.text_content,
+                headers: message_headers(email.headers),
+            })
+            .collect();
+        let results = match self.post("/email/batch", &request_body, emails.len()).await {
+            Ok(response) => response.json::<Vec<BatchResult>>().await,
+            Err(e) => Err(e),
+        };
+        let failure = match results {
+            Ok(results) if results.len() == emails.len() => {
+                return results.into_iter().map(BatchResult::into_outcome).collect()
+            }
+            // We cannot tell which messages went out: retrying could send
+            // some of them twice.
+            Ok(results) => SendError::RequestFailed {
+                message: format!(
+                    "The email provider returned {} results for {} messages.",
+                    results.len(),
+                    emails.len()
+                ),
+                is_transient: false,
+            },
+            Err(e) => e.into(),
+        };
+        emails.iter().map(|_| Err(failure.clone())).collect()
+    }
+
+    /// POST `body` to `path` once the throttle lets `n_messages` through,
+    /// sending it again if the provider asks us to slow down.
+    async fn post<T: serde::Serialize + ?Sized>(
+        &self,
+        path: &str,
+        body: &T,
+        n_messages: usize,
+    ) -> Result<Response, reqwest::Error> {
+        let url = self.base_url.join(path).unwrap();
+        let mut n_retries = 0;
+        loop {
+            let permit = self.throttle.acquire(n_messages).await;
+            let response = self
+                .http_client
+                .post(url.clone())
+                .header("X-Postmark-Server-Token", &self.authorization_token)
+                .json(body)
+                .send()
+                .await?;
+            drop(permit);
+            if response.status() == StatusCode::TOO_MANY_REQUESTS
+                && n_retries < MAX_RATE_LIMITED_RETRIES
+            {
+                if let Some(delay) = retry_after(&response).filter(|d| *d <= MAX_RETRY_AFTER) {
+                    tracing::warn!(
+                        retry_after_seconds = delay.as_secs_f64(),
+                        "The email provider is rate limiting us. Slowing down."
+                    );
+                    self.throttle.pause_for(delay);
+                    n_retries += 1;
+                    continue;
+                }
+            }
+            return response.error_for_status();
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkClient {
+    /// Send an email, waiting for the throttle to let it through.
+    ///
+    /// A `429 Too Many Requests` response carrying a `Retry-After` header
+    /// pauses every send from this client for the requested time, then the
+    /// message is sent again.
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), SendError> {
+        let request_body = SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: recipient.as_ref(),
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers: message_headers(headers),
+        };
+        self.post("/email", &request_body, 1).await?;
+        Ok(())
+    }
+
+    /// Messages are sent in chunks of at most `MAX_BATCH_SIZE`: a failed
+    /// chunk fails all of its messages, while the provider can reject single
+    /// messages of a chunk it accepted.
+    async fn send_email_batch(&self, emails: &[Email<'_>]) -> Vec<Result<(), SendError>> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for chunk in emails.chunks(MAX_BATCH_SIZE) {
+            outcomes.extend(self.send_chunk(chunk).await);
+        }
+        outcomes
+    }
+
+    fn max_batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+}
+
+impl From<reqwest::Error> for SendError {
+    fn from(e: reqwest::Error) -> Self {
+        SendError::RequestFailed {
+            message: e.to_string(),
+            is_transient: is_transient(&e),
+        }
+    }
+}
+
+/// Timeouts, connection errors, rate limiting and server-side errors are
+/// worth retrying; any other 4xx response is not going to change on its own,
+/// nor is a response body we cannot make sense of.
+fn is_transient(e: &reqwest::Error) -> bool {
+    if e.is_decode() {
+        return false;
+    }
+    match e.status() {
+        Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+        None => true,
+    }
+}
+
+/// Spaces out requests evenly and caps how many are in flight at once.
+struct Throttle {
+    in_flight: Semaphore,
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl Throttle {
+    fn new(settings: ThrottleSettings) -> Self {
+        Self {
+            in_flight: Semaphore::new(settings.max_concurrent_requests.max(1)),
+            interval: Duration::from_secs(1) / settings.max_messages_per_second.max(1),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for a free request slot and for `n_messages` to fit in the rate.
+    async fn acquire(&self, n_messages: usize) -> SemaphorePermit<'_> {
+        let permit = self
+            .in_flight
+            .acquire()
+            .await
+            .expect("The throttle semaphore is never closed.");
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + self.interval * n_messages.max(1) as u32;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+        permit
+    }
+
+    fn pause_for(&self, delay: Duration) {
+        let mut next_slot = self.next_slot.lock().unwrap();
+        *next_slot = (*next_slot).max(Instant::now() + delay);
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.signed_duration_since(chrono::Utc::now());
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<MessageHeader<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MessageHeader<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+fn message_headers(headers: &[EmailHeader]) -> Vec<MessageHeader<'_>> {
+    headers
+        .iter()
+        .map(|h| MessageHeader {
+            name: &h.name,
+            value: &h.value,
+        })
+        .collect()
+}
+
+/// The outcome of a message of a batch, in the order they were sent.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchResult {
+    error_code: i64,
+    message: String,
+}
+
+impl BatchResult {
+    fn into_outcome(self) -> Result<(), SendError> {
+        match self.error_code {
+            0 => Ok(()),
+            error_code => Err(SendError::Rejected {
+                error_code,
+                message: self.message,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PostmarkClient, ThrottleSettings, MAX_BATCH_SIZE};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Email, EmailHeader, EmailTransport, SendError};
+    use claim::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use std::time::{Duration, Instant};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+    }
+
+    #[tokio::test]
+    async fn send_email_passes_custom_headers_on() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(body_partial_json(serde_json::json!({
+                "Headers": [{ "Name": "List-Unsubscribe", "Value": "<https://example.com/u>" }]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe".into(),
+            value: "<https://example.com/u>".into(),
+        }];
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        //Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_waits_and_retries_when_told_to_retry_after() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let start = Instant::now();
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_rate_limited_without_retry_after() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_gives_up_if_rate_limited_for_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn sends_are_spaced_out_to_respect_the_rate_limit() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = throttled_email_client(
+            mock_server.uri(),
+            ThrottleSettings {
+                max_messages_per_second: 5,
+                max_concurrent_requests: 10,
+            },
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(4)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let start = Instant::now();
+        for _ in 0..4 {
+            email_client
+                .send_email(&email(), &subject(), &content(), &content(), &[])
+                .await
+                .unwrap();
+        }
+
+        // Assert - 4 messages at 5 per second take at least 3 intervals of 200ms
+        assert!(start.elapsed() >= Duration::from_millis(600));
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_are_capped() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = throttled_email_client(
+            mock_server.uri(),
+            ThrottleSettings {
+                max_messages_per_second: 1000,
+                max_concurrent_requests: 1,
+            },
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(100)))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let start = Instant::now();
+        let (email_1, email_2, email_3) = (email(), email(), email());
+        let (subject, content) = (subject(), content());
+        let outcomes = tokio::join!(
+            email_client.send_email(&email_1, &subject, &content, &content, &[]),
+            email_client.send_email(&email_2, &subject, &content, &content, &[]),
+            email_client.send_email(&email_3, &subject, &content, &content, &[]),
+        );
+
+        // Assert - The three requests went out one after the other
+        assert_ok!(outcomes.0);
+        assert_ok!(outcomes.1);
+        assert_ok!(outcomes.2);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .and(SendEmailBatchBodyMatcher)
+            .respond_with(AcceptEveryEmail)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let (recipients, subject, content) = (vec![email(), email()], subject(), content());
+        let outcomes = email_client
+            .send_email_batch(&batch(&recipients, &subject, &content))
+            .await;
+
+        // Assert
+        assert_eq!(outcomes.len(), 2);
+        assert_ok!(&outcomes[0]);
+        assert_ok!(&outcomes[1]);
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_splits_large_batches() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email/batch"))
+            .respond_with(AcceptEveryEmail)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let recipients: Vec<_> = (0..MAX_BATCH_SIZE + 1).map(|_| email()).collect();
+        let (subject, content) = (subject(), content());
+        let outcomes = email_client
+            .send_email_batch(&batch(&recipients, &subject, &content))
+            .await;
+
+        // Assert
+        assert_eq!(outcomes.len(), MAX_BATCH_SIZE + 1);
+        assert!(outcomes.iter().all(Result::is_ok));
+        let requests = mock_server.received_requests().await.unwrap();
+        let sizes: Vec<usize> = requests
+            .iter()
+            .map(|r| {
+                serde_json::from_slice::<Vec<serde_json::Value>>(&r.body)
+                    .unwrap()
+                    .len()
+            })
+            .collect();
+        assert_eq!(sizes, vec![MAX_BATCH_SIZE, 1]);
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_reports_rejected_messages() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+                { "ErrorCode": 406, "Message": "You tried to send to an inactive recipient." },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let (recipients, subject, content) = (vec![email(), email()], subject(), content());
+        let outcomes = email_client
+            .send_email_batch(&batch(&recipients, &subject, &content))
+            .await;
+
+        // Assert
+        assert_ok!(&outcomes[0]);
+        let error = outcomes[1].as_ref().unwrap_err();
+        assert!(matches!(
+            error,
+            SendError::Rejected {
+                error_code: 406,
+                ..
+            }
+        ));
+        assert!(!error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_fails_every_message_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let (recipients, subject, content) = (vec![email(), email()], subject(), content());
+        let outcomes = email_client
+            .send_email_batch(&batch(&recipients, &subject, &content))
+            .await;
+
+        // Assert
+        assert_eq!(outcomes.len(), 2);
+        for outcome in outcomes {
+            assert!(assert_err!(outcome).is_transient());
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_does_not_retry_responses_it_cannot_match() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let (recipients, subject, content) = (vec![email()], subject(), content());
+        let outcomes = email_client
+            .send_email_batch(&batch(&recipients, &subject, &content))
+            .await;
+
+        // Assert
+        let error = outcomes[0].as_ref().unwrap_err();
+        assert!(!error.is_transient());
+    }
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    struct SendEmailBatchBodyMatcher;
+
+    impl wiremock::Match for SendEmailBatchBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<Vec<serde_json::Value>, _> = serde_json::from_slice(&request.body);
+
+            if let Ok(body) = result {
+                body.iter().all(|message| {
+                    message.get("From").is_some()
+                        && message.get("To").is_some()
+                        && message.get("Subject").is_some()
+                        && message.get("HtmlBody").is_some()
+                        && message.get("TextBody").is_some()
+                })
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Replies to a batch the way Postmark does when it accepts every message.
+    struct AcceptEveryEmail;
+
+    impl wiremock::Respond for AcceptEveryEmail {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let results: Vec<_> = messages
+                .iter()
+                .map(|m| serde_json::json!({ "ErrorCode": 0, "Message": "OK", "To": m["To"] }))
+                .collect();
+            ResponseTemplate::new(200).set_body_json(results)
+        }
+    }
+
+    fn batch<'a>(
+        recipients: &'a [SubscriberEmail],
+        subject: &'a str,
+        content: &'a str,
+    ) -> Vec<Email<'a>> {
+        recipients
+            .iter()
+            .map(|recipient| Email {
+                recipient,
+                subject,
+                html_content: content,
+                text_content: content,
+                headers: &[],
+            })
+            .collect()
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> PostmarkClient {
+        throttled_email_client(
+            base_url,
+            ThrottleSettings {
+                max_messages_per_second: 1000,
+                max_concurrent_requests: 10,
+            },
+        )
+    }
+
+    fn throttled_email_client(base_url: String, throttle: ThrottleSettings) -> PostmarkClient {
+        PostmarkClient::new(
+            base_url,
+            email(),
+            Faker.fake(),
+            std::time::Duration::from_millis(200),
+            throttle,
+        )
+    }
+}
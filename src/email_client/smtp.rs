@@ -0,0 +1,488 @@
+use super::{mime_message, EmailHeader, EmailTransport, SendError};
+use crate::configuration::{SmtpSettings, SmtpTls};
+use crate::domain::SubscriberEmail;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::transport::smtp::PoolConfig;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::ExposeSecret;
+use std::time::Duration;
+
+/// Sends emails through an SMTP relay.
+///
+/// Connections are pooled: a batch goes out over the same connection(s)
+/// instead of opening one per message.
+pub struct SmtpClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+}
+
+impl SmtpClient {
+    pub fn new(
+        sender: SubscriberEmail,
+        settings: &SmtpSettings,
+        timeout: Duration,
+    ) -> Result<Self, lettre::transport::smtp::Error> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            .port(settings.port)
+            .tls(tls(settings)?)
+            .timeout(Some(timeout))
+            .pool_config(PoolConfig::new().max_size(settings.max_connections));
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder
+                .credentials(Credentials::new(
+                    username.clone(),
+                    password.expose_secret().clone(),
+                ))
+                .authentication(vec![Mechanism::Plain, Mechanism::Login]);
+        }
+        Ok(Self {
+            transport: builder.build(),
+            sender: sender
+                .as_ref()
+                .parse()
+                .expect("The sender address is not a valid mailbox."),
+        })
+    }
+}
+
+/// How the connection to the relay is secured.
+fn tls(settings: &SmtpSettings) -> Result<Tls, lettre::transport::smtp::Error> {
+    Ok(match settings.tls {
+        SmtpTls::None => Tls::None,
+        SmtpTls::StartTls => Tls::Required(TlsParameters::new(settings.host.clone())?),
+        SmtpTls::Implicit => Tls::Wrapper(TlsParameters::new(settings.host.clone())?),
+    })
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpClient {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), SendError> {
+        let message = mime_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for SendError {
+    /// `5xx` replies are permanent, while `4xx` replies, timeouts and
+    /// connection errors are worth retrying.
+    fn from(e: lettre::transport::smtp::Error) -> Self {
+        match e.status() {
+            Some(code) if e.is_permanent() => SendError::Rejected {
+                error_code: code.to_string().parse().unwrap_or_default(),
+                message: e.to_string(),
+            },
+            _ => SendError::RequestFailed {
+                message: e.to_string(),
+                is_transient: !e.is_permanent(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tls, SmtpClient};
+    use crate::configuration::{SmtpSettings, SmtpTls};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailTransport, SendError};
+    use claim::assert_ok;
+    use lettre::transport::smtp::client::Tls;
+    use secrecy::Secret;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn send_email_sends_a_multipart_alternative_message() {
+        // Arrange
+        let sink = SmtpSink::start().await;
+        let client = smtp_client(&sink, None);
+
+        // Act
+        let outcome = client
+            .send_email(
+                &email("ursula@example.com"),
+                "Newsletter title",
+                "<p>HTML body</p>",
+                "Plain body",
+                &[],
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let messages = sink.messages();
+        assert_eq!(messages.len(), 1);
+        let (recipients, data) = &messages[0];
+        assert_eq!(recipients, &vec!["ursula@example.com".to_string()]);
+        assert!(data.contains("Subject: Newsletter title"));
+        assert!(data.contains("multipart/alternative"));
+        let text = data.find("Plain body").expect("The text body is missing.");
+        let html = data
+            .find("<p>HTML body</p>")
+            .expect("The HTML body is missing.");
+        assert!(
+            text < html,
+            "The HTML body must be the preferred alternative."
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_adds_the_custom_headers() {
+        // Arrange
+        let sink = SmtpSink::start().await;
+        let client = smtp_client(&sink, None);
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe-Post".into(),
+            value: "List-Unsubscribe=One-Click".into(),
+        }];
+
+        // Act
+        let outcome = client
+            .send_email(
+                &email("ursula@example.com"),
+                "Subject",
+                "<p>Body</p>",
+                "Body",
+                &headers,
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let (_, data) = &sink.messages()[0];
+        assert!(data.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+    }
+
+    #[tokio::test]
+    async fn send_email_authenticates_when_given_credentials() {
+        // Arrange
+        let sink = SmtpSink::start().await;
+        let client = smtp_client(&sink, Some(("newsletter", "hunter2")));
+
+        // Act
+        let outcome = client
+            .send_email(
+                &email("ursula@example.com"),
+                "Subject",
+                "<p>Body</p>",
+                "Body",
+                &[],
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        assert_eq!(
+            *sink.logins.lock().unwrap(),
+            vec![login("PLAIN", "newsletter", "hunter2")]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_authenticates_with_servers_that_only_support_login() {
+        // Arrange
+        let sink = SmtpSink::start_with_auth("LOGIN").await;
+        let client = smtp_client(&sink, Some(("newsletter", "hunter2")));
+
+        // Act
+        let outcome = client
+            .send_email(
+                &email("ursula@example.com"),
+                "Subject",
+                "<p>Body</p>",
+                "Body",
+                &[],
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        assert_eq!(
+            *sink.logins.lock().unwrap(),
+            vec![login("LOGIN", "newsletter", "hunter2")]
+        );
+        assert_eq!(sink.messages().len(), 1);
+    }
+
+    #[test]
+    fn each_tls_setting_maps_to_the_matching_lettre_mode() {
+        let settings = |mode| SmtpSettings {
+            host: "smtp.example.com".into(),
+            port: 587,
+            tls: mode,
+            username: None,
+            password: None,
+            max_connections: 1,
+        };
+        assert!(matches!(tls(&settings(SmtpTls::None)), Ok(Tls::None)));
+        assert!(matches!(
+            tls(&settings(SmtpTls::StartTls)),
+            Ok(Tls::Required(_))
+        ));
+        assert!(matches!(
+            tls(&settings(SmtpTls::Implicit)),
+            Ok(Tls::Wrapper(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn connections_are_reused_across_messages() {
+        // Arrange
+        let sink = SmtpSink::start().await;
+        let client = smtp_client(&sink, None);
+
+        // Act
+        for i in 0..3 {
+            client
+                .send_email(
+                    &email(&format!("reader-{}@example.com", i)),
+                    "Subject",
+                    "<p>Body</p>",
+                    "Body",
+                    &[],
+                )
+                .await
+                .unwrap();
+        }
+
+        // Assert
+        assert_eq!(sink.messages().len(), 3);
+        assert_eq!(sink.connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_reported_as_rejections() {
+        // Arrange
+        let sink = SmtpSink::start().await;
+        let client = smtp_client(&sink, None);
+
+        // Act
+        let outcome = client
+            .send_email(
+                &email("unknown@example.com"),
+                "Subject",
+                "<p>Body</p>",
+                "Body",
+                &[],
+            )
+            .await;
+
+        // Assert
+        let error = outcome.unwrap_err();
+        assert!(matches!(
+            error,
+            SendError::Rejected {
+                error_code: 550,
+                ..
+            }
+        ));
+        assert!(!error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn temporary_failures_are_transient() {
+        // Arrange
+        let sink = SmtpSink::start().await;
+        let client = smtp_client(&sink, None);
+
+        // Act
+        let outcome = client
+            .send_email(
+                &email("busy@example.com"),
+                "Subject",
+                "<p>Body</p>",
+                "Body",
+                &[],
+            )
+            .await;
+
+        // Assert
+        assert!(outcome.unwrap_err().is_transient());
+    }
+
+    /// The mechanism, username and password of a successful login.
+    type Login = (String, String, String);
+
+    fn login(mechanism: &str, username: &str, password: &str) -> Login {
+        (mechanism.into(), username.into(), password.into())
+    }
+
+    /// A bare-bones SMTP server that accepts every message, but those sent to
+    /// `unknown@example.com` (rejected for good) or `busy@example.com`
+    /// (rejected for now).
+    struct SmtpSink {
+        port: u16,
+        connections: Arc<AtomicUsize>,
+        logins: Arc<Mutex<Vec<Login>>>,
+        messages: Arc<Mutex<Vec<(Vec<String>, String)>>>,
+    }
+
+    impl SmtpSink {
+        async fn start() -> Self {
+            Self::start_with_auth("PLAIN LOGIN").await
+        }
+
+        /// A sink that only offers the given, space-separated, `AUTH`
+        /// mechanisms.
+        async fn start_with_auth(mechanisms: &'static str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let sink = SmtpSink {
+                port: listener.local_addr().unwrap().port(),
+                connections: Arc::default(),
+                logins: Arc::default(),
+                messages: Arc::default(),
+            };
+            let (connections, logins, messages) = (
+                sink.connections.clone(),
+                sink.logins.clone(),
+                sink.messages.clone(),
+            );
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    let (logins, messages) = (logins.clone(), messages.clone());
+                    tokio::spawn(async move {
+                        let _ = serve(stream, mechanisms, logins, messages).await;
+                    });
+                }
+            });
+            sink
+        }
+
+        fn messages(&self) -> Vec<(Vec<String>, String)> {
+            self.messages.lock().unwrap().clone()
+        }
+    }
+
+    async fn serve(
+        stream: tokio::net::TcpStream,
+        mechanisms: &str,
+        logins: Arc<Mutex<Vec<Login>>>,
+        messages: Arc<Mutex<Vec<(Vec<String>, String)>>>,
+    ) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut recipients = Vec::new();
+        writer.write_all(b"220 sink ESMTP\r\n").await?;
+        while let Some(line) = lines.next_line().await? {
+            let command = line.to_uppercase();
+            let reply = if command.starts_with("EHLO") {
+                format!("250-sink\r\n250-AUTH {}\r\n250 8BITMIME\r\n", mechanisms)
+            } else if let Some(auth) = command.strip_prefix("AUTH ") {
+                let mechanism = auth.split_whitespace().next().unwrap_or_default();
+                if !mechanisms.split_whitespace().any(|m| m == mechanism) {
+                    "504 5.5.4 Unrecognized authentication type\r\n".to_string()
+                } else if mechanism == "PLAIN" {
+                    let encoded = line.split_whitespace().nth(2).unwrap();
+                    let decoded = decode(encoded);
+                    let mut parts = decoded.split('\0').skip(1);
+                    let (username, password) = (parts.next().unwrap(), parts.next().unwrap());
+                    logins
+                        .lock()
+                        .unwrap()
+                        .push(login("PLAIN", username, password));
+                    "235 2.7.0 Authentication successful\r\n".to_string()
+                } else {
+                    // LOGIN asks for the username, then for the password.
+                    let mut answers = Vec::new();
+                    for challenge in ["Username:", "Password:"] {
+                        let prompt = format!("334 {}\r\n", base64::encode(challenge));
+                        writer.write_all(prompt.as_bytes()).await?;
+                        let answer = lines.next_line().await?.unwrap_or_default();
+                        answers.push(decode(answer.trim()));
+                    }
+                    logins
+                        .lock()
+                        .unwrap()
+                        .push(login("LOGIN", &answers[0], &answers[1]));
+                    "235 2.7.0 Authentication successful\r\n".to_string()
+                }
+            } else if command.starts_with("MAIL FROM") {
+                recipients.clear();
+                "250 2.1.0 Ok\r\n".to_string()
+            } else if command.starts_with("RCPT TO") {
+                let address =
+                    line[line.find('<').unwrap() + 1..line.find('>').unwrap()].to_string();
+                match address.as_str() {
+                    "unknown@example.com" => "550 5.1.1 No such user\r\n".to_string(),
+                    "busy@example.com" => "451 4.3.0 Try again later\r\n".to_string(),
+                    _ => {
+                        recipients.push(address);
+                        "250 2.1.5 Ok\r\n".to_string()
+                    }
+                }
+            } else if command == "DATA" {
+                writer
+                    .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                    .await?;
+                let mut data = String::new();
+                while let Some(line) = lines.next_line().await? {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                messages
+                    .lock()
+                    .unwrap()
+                    .push((std::mem::take(&mut recipients), data));
+                "250 2.0.0 Ok: queued\r\n".to_string()
+            } else if command == "QUIT" {
+                writer.write_all(b"221 2.0.0 Bye\r\n").await?;
+                return Ok(());
+            } else {
+                // NOOP, RSET and the like.
+                "250 2.0.0 Ok\r\n".to_string()
+            };
+            writer.write_all(reply.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    fn decode(encoded: &str) -> String {
+        String::from_utf8(base64::decode(encoded).unwrap()).unwrap()
+    }
+
+    fn smtp_client(sink: &SmtpSink, credentials: Option<(&str, &str)>) -> SmtpClient {
+        let settings = SmtpSettings {
+            host: "127.0.0.1".into(),
+            port: sink.port,
+            tls: SmtpTls::None,
+            username: credentials.map(|(username, _)| username.to_string()),
+            password: credentials.map(|(_, password)| Secret::new(password.to_string())),
+            max_connections: 1,
+        };
+        SmtpClient::new(
+            email("newsletter@example.com"),
+            &settings,
+            Duration::from_secs(2),
+        )
+        .unwrap()
+    }
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.to_string()).unwrap()
+    }
+}
@@ -2,4 +2,5 @@ mod key;
 pub use key::IdempotencyKey;
 mod persistence;
 pub use persistence::get_saved_response;
-pub use persistence::save_response;
\ No newline at end of file
+pub use persistence::save_response;
+pub use persistence::{try_processing, NextAction};
\ No newline at end of file
@@ -0,0 +1,981 @@
+use crate::configuration::Settings;
+use crate::domain::{DeliveryStatus, SubscriberEmail};
+use crate::email_client::{Email, EmailHeader, EmailTransport};
+use crate::list_unsubscribe::UnsubscribeTarget;
+use crate::startup::get_connection_pool;
+use crate::template::{IssueTemplates, TemplateContext};
+use crate::tracking::{
+    click_tracking_url, generate_tracking_token, inject_open_pixel, open_tracking_url,
+    rewrite_links,
+};
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::Span;
+use uuid::Uuid;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client();
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.application.hmac_secret,
+    )
+    .await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailTransport>,
+    base_url: String,
+    hmac_secret: Secret<String>,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(&pool, email_client.as_ref(), &base_url, &hmac_secret).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// How many times a delivery is attempted before it is moved to `failed_deliveries`.
+pub const MAX_DELIVERY_ATTEMPTS: i16 = 8;
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// How many queued deliveries are claimed at once.
+const BATCH_SIZE: i64 = 100;
+/// How long claimed deliveries are hidden from other workers. The claim on
+/// the whole batch is renewed before each request to the email provider, so
+/// a single request has to fit in it.
+const CLAIM_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// Claim a batch of due deliveries, send them in as few requests as the
+/// email provider allows and record the outcome of each of them.
+///
+/// Claims are short transactions that push the deliveries back in the
+/// queue: no lock is held while sending. If the worker dies, the deliveries
+/// it did not get to are picked up again once the claim runs out. Each claim
+/// carries a token: a worker whose claim ran out and was taken over by
+/// another one does not send the deliveries it lost.
+///
+/// The deliveries of each request are marked as `sending` right before it
+/// goes out, and their outcome is recorded before the next request. If the
+/// worker dies in between, the mark stays: the next worker to pick them up
+/// knows they might have gone out and moves them to `failed_deliveries` for
+/// an admin to look at, instead of sending them twice.
+///
+/// `hmac_secret` signs the one-click unsubscribe URL of each message.
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let claim_token = Uuid::new_v4();
+    let tasks = claim_tasks(pool, claim_token, BATCH_SIZE).await?;
+    if tasks.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    Span::current().record("n_tasks", &tasks.len());
+
+    let recipients = get_recipients(pool, &tasks).await?;
+    // Deliveries that are not going out are settled before anything is sent.
+    let mut transaction = pool.begin().await?;
+    let mut issues: HashMap<Uuid, NewsletterIssue> = HashMap::new();
+    let mut messages = Vec::with_capacity(tasks.len());
+    for task in &tasks {
+        if task.interrupted {
+            tracing::error!(
+                newsletter_issue_id = %task.newsletter_issue_id,
+                subscriber_email = %task.subscriber_email,
+                "A previous attempt to deliver the issue was interrupted. \
+                Not sending it again.",
+            );
+            move_task_to_failed_deliveries(&mut transaction, task, INTERRUPTED_DELIVERY_ERROR)
+                .await?;
+            continue;
+        }
+        let email = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => email,
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    newsletter_issue_id = %task.newsletter_issue_id,
+                    subscriber_email = %task.subscriber_email,
+                    "Skipping a confirmed subscriber. \
+                    Their stored contact details are invalid.",
+                );
+                complete_task(&mut transaction, task, DeliveryStatus::Skipped).await?;
+                continue;
+            }
+        };
+        if !issues.contains_key(&task.newsletter_issue_id) {
+            let issue = get_issue(pool, task.newsletter_issue_id).await?;
+            issues.insert(task.newsletter_issue_id, issue);
+        }
+        let issue = &issues[&task.newsletter_issue_id];
+        let recipient = recipients.get(&task.subscriber_email);
+        // The address might have bounced, or its owner complained, since the
+        // issue was queued.
+        if let Some(status @ ("bounced" | "complained")) = recipient.map(|r| r.status.as_str()) {
+            tracing::info!(
+                newsletter_issue_id = %task.newsletter_issue_id,
+                subscriber_email = %task.subscriber_email,
+                subscriber_status = %status,
+                "Skipping a subscriber we must not send emails to anymore.",
+            );
+            complete_task(&mut transaction, task, DeliveryStatus::Skipped).await?;
+            continue;
+        }
+        let tracking_token = if issue.track_opens || !issue.links.is_empty() {
+            tracking_token(pool, task).await?
+        } else {
+            None
+        };
+        let tracking = tracking_token.as_deref().map(|token| MessageTracking {
+            token,
+            links: &issue.links,
+            track_opens: issue.track_opens,
+        });
+        let message_recipient = MessageRecipient {
+            subscriber_id: recipient.map(|r| r.id),
+            // The subscriber might have been removed since the issue was queued.
+            name: recipient.map(|r| r.name.as_str()).unwrap_or_default(),
+            email: &email,
+            subscription_token: recipient.and_then(|r| r.subscription_token.as_deref()),
+        };
+        let content = issue.content();
+        let rendered = match render_message(
+            &content,
+            &message_recipient,
+            tracking,
+            base_url,
+            hmac_secret,
+        ) {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                tracing::error!(
+                    error.message = %e,
+                    newsletter_issue_id = %task.newsletter_issue_id,
+                    subscriber_email = %task.subscriber_email,
+                    "Failed to render the issue. Giving up.",
+                );
+                move_task_to_failed_deliveries(&mut transaction, task, &e).await?;
+                continue;
+            }
+        };
+        messages.push(OutgoingMessage {
+            task,
+            email,
+            rendered,
+        });
+    }
+
+    transaction.commit().await?;
+
+    for chunk in messages.chunks(email_client.max_batch_size()) {
+        send_chunk(pool, claim_token, email_client, &issues, chunk).await?;
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Send the messages of a single request to the email provider and record
+/// their outcome.
+#[tracing::instrument(skip_all, fields(n_messages = messages.len()))]
+async fn send_chunk(
+    pool: &PgPool,
+    claim_token: Uuid,
+    email_client: &dyn EmailTransport,
+    issues: &HashMap<Uuid, NewsletterIssue>,
+    messages: &[OutgoingMessage<'_>],
+) -> Result<(), anyhow::Error> {
+    let still_claimed = mark_as_sending(pool, claim_token, messages).await?;
+    // Deliveries can be cancelled, or claimed by another worker, while the
+    // batch goes out.
+    let messages: Vec<&OutgoingMessage> = messages
+        .iter()
+        .filter(|m| still_claimed.contains(&m.task.key()))
+        .collect();
+    if messages.is_empty() {
+        return Ok(());
+    }
+    let emails: Vec<Email> = messages
+        .iter()
+        .map(|message| Email {
+            recipient: &message.email,
+            subject: issues[&message.task.newsletter_issue_id].subject(message.task.variant_id),
+            html_content: &message.rendered.html_content,
+            text_content: &message.rendered.text_content,
+            headers: &message.rendered.headers,
+        })
+        .collect();
+    let outcomes = email_client.send_email_batch(&emails).await;
+    let mut transaction = pool.begin().await?;
+    for (message, outcome) in messages.iter().zip(outcomes) {
+        let task = message.task;
+        match outcome {
+            Ok(()) => complete_task(&mut transaction, task, DeliveryStatus::Sent).await?,
+            Err(e) if e.is_transient() && task.n_retries + 1 < MAX_DELIVERY_ATTEMPTS => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    newsletter_issue_id = %task.newsletter_issue_id,
+                    subscriber_email = %task.subscriber_email,
+                    "Failed to deliver issue to a confirmed subscriber. \
+                    Retrying later.",
+                );
+                retry_task_later(&mut transaction, claim_token, task).await?;
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    newsletter_issue_id = %task.newsletter_issue_id,
+                    subscriber_email = %task.subscriber_email,
+                    "Failed to deliver issue to a confirmed subscriber. \
+                    Giving up.",
+                );
+                move_task_to_failed_deliveries(&mut transaction, task, &e.to_string()).await?;
+            }
+        }
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+struct OutgoingMessage<'a> {
+    task: &'a DeliveryTask,
+    email: SubscriberEmail,
+    rendered: RenderedMessage,
+}
+
+/// The parts of an issue that are the same in every message.
+pub struct IssueContent<'a> {
+    pub newsletter_issue_id: Uuid,
+    pub list_id: Uuid,
+    pub slug: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+}
+
+/// Who a message is rendered for.
+pub struct MessageRecipient<'a> {
+    /// `None` if there is no subscriber to take off the list.
+    pub subscriber_id: Option<Uuid>,
+    pub name: &'a str,
+    pub email: &'a SubscriberEmail,
+    /// `None` if the recipient cannot get a personal unsubscribe link.
+    pub subscription_token: Option<&'a str>,
+}
+
+/// How opens and clicks of a single delivery are recorded.
+pub struct MessageTracking<'a> {
+    pub token: &'a str,
+    /// The ids of the tracked links of the issue, by URL.
+    pub links: &'a HashMap<String, i32>,
+    pub track_opens: bool,
+}
+
+/// A message ready to be handed over to the email provider.
+pub struct RenderedMessage {
+    pub html_content: String,
+    pub text_content: String,
+    pub headers: Vec<EmailHeader>,
+}
+
+/// Render `issue` for a single recipient: template variables, tracking and
+/// the one-click unsubscribe headers, exactly as the worker sends it.
+///
+/// Bodies are validated when an issue is published, but issues published
+/// before bodies were templated can still contain a stray `{{`: rendering
+/// them fails for every recipient, retrying would not help.
+pub fn render_message(
+    issue: &IssueContent,
+    recipient: &MessageRecipient,
+    tracking: Option<MessageTracking>,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<RenderedMessage, String> {
+    let unsubscribe_url = match recipient.subscription_token {
+        // The issue is recorded as the reason for unsubscribing.
+        Some(token) => format!(
+            "{}/subscriptions/unsubscribe?subscription_token={}&list_id={}&issue_id={}",
+            base_url, token, issue.list_id, issue.newsletter_issue_id
+        ),
+        None => format!("{}/subscriptions/unsubscribe", base_url),
+    };
+    let archive_url = format!("{}/archive/{}", base_url, issue.slug);
+    let context = TemplateContext {
+        subscriber_name: recipient.name,
+        subscriber_email: recipient.email.as_ref(),
+        unsubscribe_url: &unsubscribe_url,
+        archive_url: &archive_url,
+    };
+    let templates = IssueTemplates::parse(issue.html_content, issue.text_content)
+        .map_err(|e| format!("The issue cannot be rendered. {}", e))?;
+    let (mut html_content, text_content) = templates.render(&context);
+    if let Some(tracking) = tracking {
+        html_content = rewrite_links(&html_content, |url| {
+            let link_id = tracking.links.get(url)?;
+            Some(click_tracking_url(base_url, tracking.token, *link_id))
+        });
+        if tracking.track_opens {
+            html_content =
+                inject_open_pixel(&html_content, &open_tracking_url(base_url, tracking.token));
+        }
+    }
+    let headers = match recipient.subscriber_id {
+        Some(subscriber_id) => UnsubscribeTarget {
+            subscriber_id,
+            list_id: issue.list_id,
+            issue_id: issue.newsletter_issue_id,
+        }
+        .headers(base_url, hmac_secret),
+        None => Vec::new(),
+    };
+    Ok(RenderedMessage {
+        html_content,
+        text_content,
+        headers,
+    })
+}
+
+/// The tracking token of a delivery, created on its first attempt so
+/// that retries reuse it.
+#[tracing::instrument(skip_all)]
+async fn tracking_token(
+    pool: &PgPool,
+    task: &DeliveryTask,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET tracking_token = COALESCE(tracking_token, $3)
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        RETURNING tracking_token as "tracking_token!"
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        generate_tracking_token(),
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| r.tracking_token))
+}
+
+/// The error recorded for deliveries that might or might not have been sent.
+const INTERRUPTED_DELIVERY_ERROR: &str = "The worker stopped while the email was being sent: \
+    check whether it reached the subscriber before re-queuing it.";
+
+/// Record, for good, that the messages are about to be handed over to the
+/// email provider, and renew the claim on the rest of the batch.
+///
+/// Returns the messages that are still claimed by this worker and queued,
+/// for issues that have not been cancelled: the others must not go out.
+#[tracing::instrument(skip_all)]
+async fn mark_as_sending(
+    pool: &PgPool,
+    claim_token: Uuid,
+    messages: &[OutgoingMessage<'_>],
+) -> Result<HashSet<(Uuid, String)>, anyhow::Error> {
+    let (issue_ids, emails): (Vec<Uuid>, Vec<String>) =
+        messages.iter().map(|m| m.task.key()).unzip();
+    let claimed_until = Utc::now() + chrono::Duration::from_std(CLAIM_DURATION)?;
+    let rows = sqlx::query!(
+        r#"
+        WITH renewed AS (
+            UPDATE issue_delivery_queue
+            SET execute_after = $4
+            WHERE claim_token = $3
+            RETURNING newsletter_issue_id, subscriber_email
+        ), marked AS (
+            UPDATE issue_deliveries d
+            SET
+                status = 'sending',
+                updated_at = now()
+            FROM renewed r
+            JOIN UNNEST($1::uuid[], $2::text[]) as m(newsletter_issue_id, subscriber_email)
+                USING (newsletter_issue_id, subscriber_email)
+            JOIN newsletter_issues i USING (newsletter_issue_id)
+            WHERE
+                d.newsletter_issue_id = r.newsletter_issue_id AND
+                d.subscriber_email = r.subscriber_email AND
+                d.status = 'queued' AND
+                i.status <> 'cancelled'
+            RETURNING d.newsletter_issue_id, d.subscriber_email
+        )
+        SELECT
+            newsletter_issue_id as "newsletter_issue_id!",
+            subscriber_email as "subscriber_email!"
+        FROM marked
+        "#,
+        &issue_ids[..],
+        &emails[..],
+        claim_token,
+        claimed_until
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.newsletter_issue_id, r.subscriber_email))
+        .collect())
+}
+
+/// Exponential backoff with "equal jitter": we always wait at least half of
+/// the exponential delay, and a random amount on top of it so that tasks that
+/// failed together do not all come back at the same time.
+fn retry_delay(n_retries: i16) -> Duration {
+    let exponent = n_retries.clamp(0, 16) as u32;
+    let delay = BASE_RETRY_DELAY
+        .saturating_mul(2u32.pow(exponent))
+        .min(MAX_RETRY_DELAY);
+    let half = delay / 2;
+    half + half.mul_f64(thread_rng().gen::<f64>())
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+    /// The subject line variant to send, for issues testing subject lines.
+    variant_id: Option<i16>,
+    /// Whether a worker died while sending it.
+    interrupted: bool,
+}
+
+impl DeliveryTask {
+    fn key(&self) -> (Uuid, String) {
+        (self.newsletter_issue_id, self.subscriber_email.clone())
+    }
+}
+
+/// Take due deliveries out of the way of other workers for
+/// [`CLAIM_DURATION`], under `claim_token`.
+#[tracing::instrument(skip_all)]
+async fn claim_tasks(
+    pool: &PgPool,
+    claim_token: Uuid,
+    limit: i64,
+) -> Result<Vec<DeliveryTask>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    drop_cancelled_tasks(&mut transaction).await?;
+    // `SKIP LOCKED` lets several workers share the queue: rows being claimed
+    // by another transaction are ignored instead of blocking this one.
+    // The deliveries of paused issues stay in the queue until they resume.
+    let tasks = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_email,
+            q.n_retries,
+            d.variant_id,
+            COALESCE(d.status = 'sending', false) as "interrupted!"
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        LEFT JOIN issue_deliveries d USING (newsletter_issue_id, subscriber_email)
+        WHERE
+            q.execute_after <= now() AND
+            NOT i.delivery_paused
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    let (issue_ids, emails): (Vec<Uuid>, Vec<String>) = tasks.iter().map(|t| t.key()).unzip();
+    let claimed_until = Utc::now() + chrono::Duration::from_std(CLAIM_DURATION)?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue q
+        SET
+            execute_after = $3,
+            claim_token = $4
+        FROM UNNEST($1::uuid[], $2::text[]) as m(newsletter_issue_id, subscriber_email)
+        WHERE
+            q.newsletter_issue_id = m.newsletter_issue_id AND
+            q.subscriber_email = m.subscriber_email
+        "#,
+        &issue_ids[..],
+        &emails[..],
+        claimed_until,
+        claim_token
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(tasks)
+}
+
+/// Remove the deliveries that cancelling an issue skipped because a worker
+/// was busy with them.
+///
+/// Deliveries marked as `sending` stay: they might have gone out, and are
+/// moved to `failed_deliveries` once their claim runs out.
+#[tracing::instrument(skip_all)]
+async fn drop_cancelled_tasks(transaction: &mut PgTransaction) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        WITH dropped AS (
+            DELETE FROM issue_delivery_queue
+            WHERE (newsletter_issue_id, subscriber_email) IN (
+                SELECT q.newsletter_issue_id, q.subscriber_email
+                FROM issue_delivery_queue q
+                JOIN newsletter_issues i USING (newsletter_issue_id)
+                LEFT JOIN issue_deliveries d USING (newsletter_issue_id, subscriber_email)
+                WHERE
+                    i.status = 'cancelled' AND
+                    d.status IS DISTINCT FROM 'sending'
+                FOR UPDATE OF q
+                SKIP LOCKED
+            )
+            RETURNING newsletter_issue_id, subscriber_email
+        )
+        UPDATE issue_deliveries d
+        SET
+            status = 'cancelled',
+            updated_at = now()
+        FROM dropped
+        WHERE
+            d.newsletter_issue_id = dropped.newsletter_issue_id AND
+            d.subscriber_email = dropped.subscriber_email
+        "#
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Record the final status of a delivery and remove it from the queue.
+#[tracing::instrument(skip_all)]
+async fn complete_task(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    status: DeliveryStatus,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET
+            status = $3,
+            updated_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        status.as_str()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    delete_task(transaction, task).await
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// The delivery goes back to the queue, unless its issue has been cancelled
+/// in the meantime. It is released from the claim, which would otherwise
+/// override its retry delay when it is renewed.
+#[tracing::instrument(skip_all)]
+async fn retry_task_later(
+    transaction: &mut PgTransaction,
+    claim_token: Uuid,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(retry_delay(task.n_retries))?;
+    let n_requeued = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = $3,
+            claim_token = NULL
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2 AND
+            claim_token = $4
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        execute_after,
+        claim_token
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if n_requeued == 0 {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET
+            status = 'queued',
+            updated_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn move_task_to_failed_deliveries(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO failed_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            n_attempts,
+            last_error,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            n_attempts = failed_deliveries.n_attempts + EXCLUDED.n_attempts,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        task.n_retries + 1,
+        error
+    )
+    .execute(&mut *transaction)
+    .await?;
+    complete_task(transaction, task, DeliveryStatus::Failed).await
+}
+
+/// Queue one delivery per recipient of an issue that is going out: the
+/// confirmed members of its list, narrowed down by its tag filters.
+///
+/// Issues testing subject lines only go to the sample of the test: the rest
+/// of the list is queued by [`enqueue_remaining_deliveries`] once a winner
+/// has been picked.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let subject_test = sqlx::query!(
+        r#"
+        UPDATE subject_tests
+        SET test_ends_at = now() + make_interval(hours => test_duration_hours)
+        WHERE newsletter_issue_id = $1
+        RETURNING
+            sample_percent,
+            (
+                SELECT COUNT(*)
+                FROM subject_variants v
+                WHERE v.newsletter_issue_id = $1
+            ) as "n_variants!"
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    match subject_test {
+        Some(test) => {
+            enqueue_subject_test_sample(
+                transaction,
+                newsletter_issue_id,
+                test.sample_percent,
+                test.n_variants,
+            )
+            .await
+        }
+        None => enqueue_remaining_deliveries(transaction, newsletter_issue_id, None).await,
+    }
+}
+
+/// Queue a delivery for every recipient of the issue who has not been sent it
+/// yet, using the given subject line variant.
+#[tracing::instrument(skip(transaction))]
+pub async fn enqueue_remaining_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    variant_id: Option<i16>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        WITH deliveries AS (
+            INSERT INTO issue_deliveries (
+                newsletter_issue_id,
+                subscriber_email,
+                status,
+                updated_at,
+                variant_id
+            )
+            SELECT $1, r.email, 'queued', now(), $2
+            FROM newsletter_issues i,
+                issue_recipients(i.list_id, i.include_tags, i.exclude_tags) r
+            WHERE i.newsletter_issue_id = $1
+            ON CONFLICT DO NOTHING
+            RETURNING newsletter_issue_id, subscriber_email
+        )
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT newsletter_issue_id, subscriber_email
+        FROM deliveries
+        "#,
+        newsletter_issue_id,
+        variant_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Queue deliveries for a random `sample_percent` of the recipients of the
+/// issue, sending each variant to an equal share of them.
+#[tracing::instrument(skip(transaction))]
+async fn enqueue_subject_test_sample(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    sample_percent: i16,
+    n_variants: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        WITH recipients AS (
+            SELECT
+                r.email,
+                row_number() OVER (ORDER BY random()) - 1 as n,
+                COUNT(*) OVER () as total
+            FROM newsletter_issues i,
+                issue_recipients(i.list_id, i.include_tags, i.exclude_tags) r
+            WHERE i.newsletter_issue_id = $1
+        ), deliveries AS (
+            INSERT INTO issue_deliveries (
+                newsletter_issue_id,
+                subscriber_email,
+                status,
+                updated_at,
+                variant_id
+            )
+            SELECT $1, email, 'queued', now(), (n % $3 + 1)::smallint
+            FROM recipients
+            WHERE n < ceil(total * $2::smallint / 100.0)
+            RETURNING newsletter_issue_id, subscriber_email
+        )
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT newsletter_issue_id, subscriber_email
+        FROM deliveries
+        "#,
+        newsletter_issue_id,
+        sample_percent,
+        n_variants,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+struct NewsletterIssue {
+    newsletter_issue_id: Uuid,
+    list_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    slug: String,
+    track_opens: bool,
+    /// The ids of the tracked links of the issue, by URL. Empty unless
+    /// clicks are tracked.
+    links: HashMap<String, i32>,
+    subject_variants: HashMap<i16, String>,
+}
+
+impl NewsletterIssue {
+    fn content(&self) -> IssueContent {
+        IssueContent {
+            newsletter_issue_id: self.newsletter_issue_id,
+            list_id: self.list_id,
+            slug: &self.slug,
+            html_content: &self.html_content,
+            text_content: &self.text_content,
+        }
+    }
+
+    /// Issues that do not test subject lines go out with their title.
+    fn subject(&self, variant_id: Option<i16>) -> &str {
+        variant_id
+            .and_then(|id| self.subject_variants.get(&id))
+            .unwrap_or(&self.title)
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT list_id, title, text_content, html_content, slug, track_opens
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    let links = sqlx::query!(
+        r#"
+        SELECT link_id, url
+        FROM issue_links
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| (r.url, r.link_id))
+    .collect();
+    let subject_variants = sqlx::query!(
+        r#"
+        SELECT variant_id, subject
+        FROM subject_variants
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| (r.variant_id, r.subject))
+    .collect();
+    Ok(NewsletterIssue {
+        newsletter_issue_id: issue_id,
+        list_id: issue.list_id,
+        title: issue.title,
+        text_content: issue.text_content,
+        html_content: issue.html_content,
+        slug: issue.slug,
+        track_opens: issue.track_opens,
+        links,
+        subject_variants,
+    })
+}
+
+struct Recipient {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscription_token: Option<String>,
+}
+
+/// The subscribers the tasks are addressed to, by email address.
+///
+/// The columns of `subscriptions` are marked as non-null: the planner may
+/// put it on the inner side of the join, and sqlx would then infer them
+/// as nullable.
+#[tracing::instrument(skip_all)]
+async fn get_recipients(
+    pool: &PgPool,
+    tasks: &[DeliveryTask],
+) -> Result<HashMap<String, Recipient>, anyhow::Error> {
+    let emails: Vec<String> = tasks.iter().map(|t| t.subscriber_email.clone()).collect();
+    let recipients = sqlx::query_as!(
+        Recipient,
+        r#"
+        SELECT
+            s.id as "id!",
+            s.email as "email!",
+            s.name as "name!",
+            s.status as "status!",
+            t.subscription_token as "subscription_token?"
+        FROM subscriptions s
+        LEFT JOIN subscription_tokens t ON t.subscriber_id = s.id
+        WHERE s.email = ANY($1)
+        "#,
+        &emails[..]
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(recipients
+        .into_iter()
+        .map(|recipient| (recipient.email.clone(), recipient))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_delay, BASE_RETRY_DELAY, MAX_RETRY_DELAY};
+
+    #[test]
+    fn retry_delay_grows_exponentially() {
+        for n_retries in 0..5 {
+            let expected = BASE_RETRY_DELAY * 2u32.pow(n_retries as u32);
+            let delay = retry_delay(n_retries);
+            assert!(delay >= expected / 2, "{:?} is shorter than {:?}", delay, expected / 2);
+            assert!(delay <= expected, "{:?} is longer than {:?}", delay, expected);
+        }
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        for n_retries in [10, 100, i16::MAX] {
+            assert!(retry_delay(n_retries) <= MAX_RETRY_DELAY);
+        }
+    }
+}
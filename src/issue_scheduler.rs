@@ -0,0 +1,208 @@
+use crate::configuration::Settings;
+use crate::issue_delivery_worker::{enqueue_delivery_tasks, enqueue_remaining_deliveries};
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::time::Duration;
+use tracing::field::display;
+use tracing::Span;
+
+pub enum ReleaseOutcome {
+    IssueReleased,
+    NothingDue,
+}
+
+pub async fn run_scheduler_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    scheduler_loop(connection_pool).await
+}
+
+async fn scheduler_loop(pool: PgPool) -> Result<(), anyhow::Error> {
+    loop {
+        let released = try_release_issue(&pool).await;
+        let completed = try_complete_subject_test(&pool).await;
+        match (released, completed) {
+            (Err(_), _) | (_, Err(_)) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            (Ok(ReleaseOutcome::NothingDue), Ok(ReleaseOutcome::NothingDue)) => {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Enqueue the deliveries of a scheduled issue whose publishing time has come.
+#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty), err)]
+pub async fn try_release_issue(pool: &PgPool) -> Result<ReleaseOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // Rescheduling or cancelling an issue waits for this lock to be released,
+    // so an issue can never be both released and cancelled.
+    let issue = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE
+            status = 'scheduled' AND
+            published_at <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let issue_id = match issue {
+        Some(issue) => issue.newsletter_issue_id,
+        None => return Ok(ReleaseOutcome::NothingDue),
+    };
+    Span::current().record("newsletter_issue_id", &display(issue_id));
+
+    enqueue_delivery_tasks(&mut transaction, issue_id).await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'published',
+            updated_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(ReleaseOutcome::IssueReleased)
+}
+
+/// How a subject line variant fared with the sample of its test.
+#[derive(Debug)]
+pub struct VariantResult {
+    pub variant_id: i16,
+    pub n_sent: i64,
+    pub n_opened: i64,
+}
+
+/// Send an issue testing subject lines to the rest of its recipients, with
+/// the winning subject line, once its test is over.
+#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty), err)]
+pub async fn try_complete_subject_test(pool: &PgPool) -> Result<ReleaseOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // Locking the issue as well keeps it from being cancelled while the rest
+    // of its list is queued.
+    let test = sqlx::query!(
+        r#"
+        SELECT t.newsletter_issue_id
+        FROM subject_tests t
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE
+            t.winning_variant_id IS NULL AND
+            t.test_ends_at <= now() AND
+            i.status = 'published'
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let issue_id = match test {
+        Some(test) => test.newsletter_issue_id,
+        None => return Ok(ReleaseOutcome::NothingDue),
+    };
+    Span::current().record("newsletter_issue_id", &display(issue_id));
+
+    let results = sqlx::query_as!(
+        VariantResult,
+        r#"
+        SELECT
+            v.variant_id,
+            COUNT(*) FILTER (WHERE d.status = 'sent') as "n_sent!",
+            COUNT(d.first_opened_at) as "n_opened!"
+        FROM subject_variants v
+        LEFT JOIN issue_deliveries d
+            ON d.newsletter_issue_id = v.newsletter_issue_id AND
+               d.variant_id = v.variant_id
+        WHERE v.newsletter_issue_id = $1
+        GROUP BY v.variant_id
+        "#,
+        issue_id
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    let winner = pick_winner(&results).context("A subject test has no variants.")?;
+    tracing::info!(
+        winning_variant_id = winner,
+        "The subject line test is over."
+    );
+
+    enqueue_remaining_deliveries(&mut transaction, issue_id, Some(winner)).await?;
+    sqlx::query!(
+        r#"
+        UPDATE subject_tests
+        SET winning_variant_id = $2
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        winner
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(ReleaseOutcome::IssueReleased)
+}
+
+/// The variant with the best open rate, ties going to the variant listed
+/// first.
+pub fn pick_winner(results: &[VariantResult]) -> Option<i16> {
+    let open_rate = |r: &VariantResult| {
+        if r.n_sent == 0 {
+            0.0
+        } else {
+            r.n_opened as f64 / r.n_sent as f64
+        }
+    };
+    let mut results: Vec<&VariantResult> = results.iter().collect();
+    results.sort_by_key(|r| r.variant_id);
+    results
+        .into_iter()
+        .fold(None, |best: Option<&VariantResult>, r| match best {
+            Some(best) if open_rate(best) >= open_rate(r) => Some(best),
+            _ => Some(r),
+        })
+        .map(|r| r.variant_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pick_winner, VariantResult};
+
+    fn result(variant_id: i16, n_sent: i64, n_opened: i64) -> VariantResult {
+        VariantResult {
+            variant_id,
+            n_sent,
+            n_opened,
+        }
+    }
+
+    #[test]
+    fn the_best_open_rate_wins() {
+        let results = vec![result(1, 10, 2), result(2, 8, 4), result(3, 10, 3)];
+        assert_eq!(pick_winner(&results), Some(2));
+    }
+
+    #[test]
+    fn ties_go_to_the_first_variant() {
+        let results = vec![result(2, 10, 5), result(1, 4, 2)];
+        assert_eq!(pick_winner(&results), Some(1));
+    }
+
+    #[test]
+    fn variants_nobody_was_sent_do_not_win() {
+        let results = vec![result(1, 0, 0), result(2, 10, 1)];
+        assert_eq!(pick_winner(&results), Some(2));
+        assert_eq!(pick_winner(&[result(1, 0, 0), result(2, 0, 0)]), Some(1));
+        assert_eq!(pick_winner(&[]), None);
+    }
+}
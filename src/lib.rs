@@ -2,9 +2,15 @@ pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod list_unsubscribe;
+pub mod markdown;
 pub mod routes;
 mod session_state;
 pub mod startup;
 pub mod telemetry;
+pub mod template;
+pub mod tracking;
 mod utils;
 pub mod idempotency;
\ No newline at end of file
@@ -0,0 +1,285 @@
+//! Render the Markdown body of a newsletter issue into the two bodies of an
+//! email: sanitized HTML and a plain-text alternative.
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag};
+
+/// Render Markdown to HTML that is safe to send out and to serve.
+///
+/// Raw HTML is allowed in Markdown, so the output goes through `ammonia` to
+/// strip scripts, event handlers and the like.
+pub fn to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new(markdown));
+    restore_template_tags(&ammonia::clean(&html))
+}
+
+/// Link targets are percent-encoded when rendered, which mangles template
+/// tags such as `[unsubscribe]({{unsubscribe_url}})`: put them back.
+///
+/// Only `href` and `src` attributes that start with a tag are restored,
+/// encoded braces anywhere else were written that way.
+fn restore_template_tags(html: &str) -> String {
+    let mut restored = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(value_start) = next_url_attribute(rest) {
+        restored.push_str(&rest[..value_start]);
+        rest = &rest[value_start..];
+        let value = &rest[..rest.find('"').unwrap_or(rest.len())];
+        let tag = value
+            .strip_prefix("%7B%7B")
+            .and_then(|after_open| Some(&after_open[..after_open.find("%7D%7D")?]));
+        if let Some(tag) = tag {
+            restored.push_str("{{");
+            restored.push_str(&tag.replace("%20", " "));
+            restored.push_str("}}");
+            rest = &rest[tag.len() + 12..];
+        }
+    }
+    restored.push_str(rest);
+    restored
+}
+
+/// Where the value of the next `href` or `src` attribute starts.
+fn next_url_attribute(html: &str) -> Option<usize> {
+    [" href=\"", " src=\""]
+        .iter()
+        .filter_map(|attribute| html.find(attribute).map(|i| i + attribute.len()))
+        .min()
+}
+
+/// Render Markdown to plain text meant to be read as is in an email client.
+///
+/// Headings are underlined, links and images are numbered and listed as
+/// footnotes at the end, raw HTML is dropped.
+pub fn to_text(markdown: &str) -> String {
+    let mut renderer = TextRenderer::default();
+    for event in Parser::new(markdown) {
+        renderer.handle(event);
+    }
+    renderer.finish()
+}
+
+#[derive(Default)]
+struct TextRenderer {
+    out: String,
+    /// The next item number of each enclosing list, `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    /// Where the text of each enclosing heading, block quote, code block or
+    /// link starts in `out`, they are post-processed once they end.
+    starts: Vec<usize>,
+    footnotes: Vec<String>,
+    /// Set right after a list marker, so that the first paragraph of a loose
+    /// list item stays on the marker's line.
+    at_item_start: bool,
+}
+
+impl TextRenderer {
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) | Event::Code(text) => self.push(&text),
+            Event::SoftBreak => self.push(" "),
+            Event::HardBreak => self.out.push('\n'),
+            Event::Rule => {
+                self.start_block();
+                self.out.push_str("----------");
+            }
+            Event::TaskListMarker(checked) => self.push(if checked { "[x] " } else { "[ ] " }),
+            Event::Html(_) | Event::FootnoteReference(_) => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {
+                if !self.at_item_start {
+                    self.start_block();
+                }
+            }
+            Tag::Heading(..) | Tag::BlockQuote | Tag::CodeBlock(_) => {
+                self.start_block();
+                self.starts.push(self.out.len());
+            }
+            Tag::List(first_number) => {
+                if self.lists.is_empty() {
+                    self.start_block();
+                }
+                self.lists.push(first_number);
+            }
+            Tag::Item => {
+                if !self.out.is_empty() && !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.out.push_str(&indent);
+                self.out.push_str(&marker);
+                self.at_item_start = true;
+            }
+            Tag::Link(..) | Tag::Image(..) => self.starts.push(self.out.len()),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading(level, ..) => {
+                let start = self.starts.pop().unwrap_or_default();
+                let width = self.out[start..].chars().count();
+                let underline = if level == HeadingLevel::H1 { "=" } else { "-" };
+                self.out.push('\n');
+                self.out.push_str(&underline.repeat(width));
+            }
+            Tag::BlockQuote => {
+                let start = self.starts.pop().unwrap_or_default();
+                let quoted = prefix_lines(self.out[start..].trim_end(), "> ");
+                self.out.replace_range(start.., &quoted);
+            }
+            Tag::CodeBlock(_) => {
+                let start = self.starts.pop().unwrap_or_default();
+                let indented = prefix_lines(self.out[start..].trim_end(), "    ");
+                self.out.replace_range(start.., &indented);
+            }
+            Tag::List(_) => {
+                self.lists.pop();
+            }
+            Tag::Link(_, destination, _) | Tag::Image(_, destination, _) => {
+                let start = self.starts.pop().unwrap_or_default();
+                let text = &self.out[start..];
+                // Autolinks and email addresses already read fine as they are.
+                if text != destination.as_ref() && !destination.starts_with("mailto:") {
+                    let n = match self.footnotes.iter().position(|f| *f == *destination) {
+                        Some(i) => i + 1,
+                        None => {
+                            self.footnotes.push(destination.to_string());
+                            self.footnotes.len()
+                        }
+                    };
+                    self.out.push_str(&format!(" [{}]", n));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        self.at_item_start = false;
+        self.out.push_str(text);
+    }
+
+    /// Separate the block about to start from the previous one by a blank line.
+    fn start_block(&mut self) {
+        self.at_item_start = false;
+        if self.out.is_empty() {
+            return;
+        }
+        // Blocks nested in a list item stay attached to it.
+        let separator = if self.lists.is_empty() { "\n\n" } else { "\n" };
+        while !self.out.ends_with(separator) {
+            self.out.push('\n');
+        }
+    }
+
+    fn finish(self) -> String {
+        let mut text = self.out.trim_end().to_string();
+        if !self.footnotes.is_empty() {
+            text.push_str("\n\n");
+            for (i, destination) in self.footnotes.iter().enumerate() {
+                text.push_str(&format!("[{}] {}\n", i + 1, destination));
+            }
+            text.truncate(text.trim_end().len());
+        }
+        text
+    }
+}
+
+fn prefix_lines(text: &str, prefix: &str) -> String {
+    text.lines()
+        .map(|line| format!("{}{}", prefix, line).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_html, to_text};
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let html = to_html("# Hello\n\nSome *emphasis*.");
+        assert_eq!(html, "<h1>Hello</h1>\n<p>Some <em>emphasis</em>.</p>\n");
+    }
+
+    #[test]
+    fn scripts_are_stripped_from_the_html() {
+        let html = to_html("Hi<script>alert('pwned')</script> <a href=\"#\" onclick=\"x()\">there</a>");
+        assert!(!html.contains("script"));
+        assert!(!html.contains("onclick"));
+        assert!(html.contains("there"));
+    }
+
+    #[test]
+    fn template_tags_survive_in_link_targets() {
+        let html = to_html("[Unsubscribe](<{{ unsubscribe_url }}>) or [read online]({{archive_url}})");
+        assert!(html.contains(r#"href="{{ unsubscribe_url }}""#));
+        assert!(html.contains(r#"href="{{archive_url}}""#));
+    }
+
+    #[test]
+    fn encoded_braces_outside_of_template_tags_are_left_alone() {
+        let html =
+            to_html("Type %7B%7Bname%7D%7D or [search](https://example.com/?q=%7B%7Bx%7D%7D)");
+        assert!(html.contains("Type %7B%7Bname%7D%7D or"));
+        assert!(html.contains(r#"href="https://example.com/?q=%7B%7Bx%7D%7D""#));
+    }
+
+    #[test]
+    fn headings_are_underlined() {
+        let text = to_text("# Title\n\n## Section\n\nBody");
+        assert_eq!(text, "Title\n=====\n\nSection\n-------\n\nBody");
+    }
+
+    #[test]
+    fn links_become_footnotes() {
+        let text = to_text(
+            "Read [the blog](https://example.com/blog) and [the docs](https://example.com/docs), \
+            then [the blog](https://example.com/blog) again.",
+        );
+        assert_eq!(
+            text,
+            "Read the blog [1] and the docs [2], then the blog [1] again.\n\n\
+            [1] https://example.com/blog\n\
+            [2] https://example.com/docs"
+        );
+    }
+
+    #[test]
+    fn autolinks_are_not_footnoted() {
+        let text = to_text("See <https://example.com>");
+        assert_eq!(text, "See https://example.com");
+    }
+
+    #[test]
+    fn lists_are_rendered_with_markers() {
+        let text = to_text("Intro\n\n- one\n- two\n  1. nested\n\nOutro");
+        assert_eq!(text, "Intro\n\n- one\n- two\n  1. nested\n\nOutro");
+    }
+
+    #[test]
+    fn block_quotes_and_code_blocks_are_prefixed() {
+        let text = to_text("> quoted\n> text\n\n```\nlet x = 1;\n```");
+        assert_eq!(text, "> quoted text\n\n    let x = 1;");
+    }
+
+    #[test]
+    fn raw_html_is_dropped_from_the_text() {
+        let text = to_text("Hello <b>world</b>");
+        assert_eq!(text, "Hello world");
+    }
+}
@@ -28,6 +28,10 @@ pub async fn admin_dashboard(
     <ol>
         <li><a href="/admin/password">Change password</a></li>
         <li><a href="/admin/newsletters">Send newsletter</a></li>
+        <li><a href="/admin/newsletters/scheduled">Scheduled newsletters</a></li>
+        <li><a href="/admin/failed_deliveries">Failed deliveries</a></li>
+        <li><a href="/admin/lists">Mailing lists</a></li>
+        <li><a href="/admin/subscribers">Subscribers</a></li>
         <li>
             <a href="javascript:document.logoutForm.submit()">Logout</a>
             <form name="logoutForm" action="/admin/logout" method="post" hidden>
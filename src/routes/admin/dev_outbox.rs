@@ -0,0 +1,136 @@
+//! Browse the emails written by the `outbox` backend, so that confirmation
+//! links can be followed without a real inbox.
+//! These pages are only mounted in the `local` environment.
+use crate::email_client::Outbox;
+use crate::utils::e500;
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use htmlescape::encode_minimal;
+
+pub async fn dev_outbox(
+    outbox: web::Data<Option<Outbox>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let content = match outbox.get_ref() {
+        None => "<p>Emails are not written to an outbox: set <code>email_client.backend</code> \
+            to <code>outbox</code> to browse them here.</p>"
+            .to_string(),
+        Some(outbox) => {
+            let messages = outbox.list().await.map_err(e500)?;
+            if messages.is_empty() {
+                "<p>The outbox is empty.</p>".to_string()
+            } else {
+                let rows: String = messages
+                    .iter()
+                    .map(|message| {
+                        format!(
+                            r#"<tr><td>{}</td><td>{}</td><td><a href="/admin/dev/outbox/{}">{}</a></td></tr>"#,
+                            encode_minimal(&message.sent_at),
+                            encode_minimal(&message.to),
+                            message.id,
+                            encode_minimal(&message.subject),
+                        )
+                    })
+                    .collect();
+                format!(
+                    r#"<table>
+        <tr><th>Sent at</th><th>To</th><th>Subject</th></tr>
+        {}
+    </table>"#,
+                    rows
+                )
+            }
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Outbox</title>
+</head>
+<body>
+    <h1>Outbox</h1>
+    {content}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            content = content,
+        )))
+}
+
+/// Show a message the way an email client would. Unlike draft previews, the
+/// frame lets links open in a new tab, so that confirmation links work.
+pub async fn dev_outbox_message(
+    message_id: web::Path<String>,
+    outbox: web::Data<Option<Outbox>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outbox = match outbox.get_ref() {
+        Some(outbox) => outbox,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let message = match outbox.get(&message_id).await.map_err(e500)? {
+        Some(message) => message,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let html_content = format!(r#"<base target="_blank">{}"#, message.html_body);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{subject}</title>
+</head>
+<body>
+    <h1>{subject}</h1>
+    <p>
+        From: {from}<br>
+        To: {to}<br>
+        Sent at: {sent_at}
+    </p>
+    <h2>HTML</h2>
+    <iframe sandbox="allow-popups allow-popups-to-escape-sandbox" width="100%" height="600" srcdoc="{html_content}"></iframe>
+    <h2>Text</h2>
+    <pre>{text_content}</pre>
+    <p><a href="/admin/dev/outbox/{id}/raw">Download the .eml file</a></p>
+    <p><a href="/admin/dev/outbox">&lt;- Back</a></p>
+</body>
+</html>"#,
+            subject = encode_minimal(&message.subject),
+            from = encode_minimal(&message.from),
+            to = encode_minimal(&message.to),
+            sent_at = encode_minimal(&message.sent_at),
+            html_content = encode_minimal(&html_content),
+            text_content = encode_minimal(&message.text_body),
+            id = message.id,
+        )))
+}
+
+/// The message exactly as it would have gone over the wire.
+pub async fn dev_outbox_message_raw(
+    message_id: web::Path<String>,
+    outbox: web::Data<Option<Outbox>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outbox = match outbox.get_ref() {
+        Some(outbox) => outbox,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    match outbox.raw(&message_id).await.map_err(e500)? {
+        Some(eml) => Ok(HttpResponse::Ok()
+            .content_type("message/rfc822")
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(format!(
+                    "{}.eml",
+                    message_id.as_str()
+                ))],
+            })
+            .body(eml)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
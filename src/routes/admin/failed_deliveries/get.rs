@@ -0,0 +1,115 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn failed_deliveries(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let failed_deliveries = get_failed_deliveries(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for delivery in &failed_deliveries {
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{title}</td>
+            <td>{email}</td>
+            <td>{n_attempts}</td>
+            <td>{last_error}</td>
+            <td>{failed_at}</td>
+            <td>
+                <form action="/admin/failed_deliveries" method="post">
+                    <input hidden type="text" name="newsletter_issue_id" value="{issue_id}">
+                    <input hidden type="text" name="subscriber_email" value="{email}">
+                    <button type="submit">Re-queue</button>
+                </form>
+            </td>
+        </tr>"#,
+            title = encode_minimal(&delivery.title),
+            email = encode_minimal(&delivery.subscriber_email),
+            n_attempts = delivery.n_attempts,
+            last_error = encode_minimal(&delivery.last_error),
+            failed_at = delivery.failed_at.to_rfc3339(),
+            issue_id = delivery.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    let body_html = if failed_deliveries.is_empty() {
+        "<p>There are no failed deliveries.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+        <tr>
+            <th>Issue</th>
+            <th>Subscriber</th>
+            <th>Attempts</th>
+            <th>Last error</th>
+            <th>Failed at</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>"#
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Failed deliveries</title>
+</head>
+<body>
+    {msg_html}
+    {body_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+        )))
+}
+
+struct FailedDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_attempts: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get failed deliveries", skip(pool))]
+async fn get_failed_deliveries(pool: &PgPool) -> Result<Vec<FailedDelivery>, anyhow::Error> {
+    let failed_deliveries = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT
+            f.newsletter_issue_id,
+            i.title,
+            f.subscriber_email,
+            f.n_attempts,
+            f.last_error,
+            f.failed_at
+        FROM failed_deliveries f
+        JOIN newsletter_issues i ON i.newsletter_issue_id = f.newsletter_issue_id
+        ORDER BY f.failed_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get failed deliveries.")?;
+    Ok(failed_deliveries)
+}
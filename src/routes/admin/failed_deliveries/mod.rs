@@ -0,0 +1,4 @@
+mod get;
+pub use get::failed_deliveries;
+mod post;
+pub use post::requeue_failed_delivery;
@@ -0,0 +1,130 @@
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+#[tracing::instrument(name = "Re-queue a failed delivery", skip(form, pool))]
+pub async fn requeue_failed_delivery(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outcome = requeue(&pool, form.newsletter_issue_id, &form.subscriber_email)
+        .await
+        .map_err(e500)?;
+    match outcome {
+        RequeueOutcome::Requeued => FlashMessage::info(format!(
+            "The delivery to {} has been re-queued.",
+            encode_minimal(&form.subscriber_email)
+        ))
+        .send(),
+        RequeueOutcome::NotFound => FlashMessage::error(format!(
+            "There is no failed delivery to {} for this issue.",
+            encode_minimal(&form.subscriber_email)
+        ))
+        .send(),
+        RequeueOutcome::IssueCancelled => FlashMessage::error(format!(
+            "The delivery to {} cannot be re-queued: the issue has been cancelled.",
+            encode_minimal(&form.subscriber_email)
+        ))
+        .send(),
+    }
+    Ok(see_other("/admin/failed_deliveries"))
+}
+
+enum RequeueOutcome {
+    Requeued,
+    NotFound,
+    IssueCancelled,
+}
+
+async fn requeue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+) -> Result<RequeueOutcome, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // The lock keeps the issue from being cancelled until the delivery is
+    // back in the queue.
+    let issue = sqlx::query!(
+        r#"
+        SELECT status
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR SHARE
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the status of the newsletter issue.")?;
+    match issue {
+        Some(issue) if issue.status == "cancelled" => return Ok(RequeueOutcome::IssueCancelled),
+        Some(_) => {}
+        None => return Ok(RequeueOutcome::NotFound),
+    }
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM failed_deliveries
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the failed delivery.")?
+    .rows_affected();
+    if deleted == 0 {
+        return Ok(RequeueOutcome::NotFound);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to enqueue the delivery task.")?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET
+            status = 'queued',
+            updated_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the delivery status.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to re-queue a failed delivery.")?;
+    Ok(RequeueOutcome::Requeued)
+}
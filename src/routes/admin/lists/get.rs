@@ -0,0 +1,60 @@
+use super::persistence::get_lists;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn mailing_lists(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr><td>{}{}</td><td>{}</td><td>{}</td><td><code>{}</code></td></tr>"#,
+            encode_minimal(&list.name),
+            if list.is_default { " (default)" } else { "" },
+            list.n_confirmed,
+            list.n_pending,
+            list.list_id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Mailing lists</title>
+</head>
+<body>
+    {msg_html}
+    <p>Subscription forms pick a list through their <code>list_id</code> field:
+    subscribers who do not name one join the default list.</p>
+    <table>
+        <tr><th>List</th><th>Confirmed</th><th>Pending confirmation</th><th>Id</th></tr>
+        {rows_html}
+    </table>
+    <form action="/admin/lists" method="post">
+        <label>Name
+            <input type="text" placeholder="Enter the name of the list" name="name">
+        </label>
+        <button type="submit">Create list</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+        )))
+}
@@ -0,0 +1,6 @@
+mod get;
+pub use get::mailing_lists;
+mod persistence;
+pub use persistence::{get_list, get_lists, MailingList};
+mod post;
+pub use post::create_mailing_list;
@@ -0,0 +1,82 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub name: String,
+    pub is_default: bool,
+    pub n_confirmed: i64,
+    pub n_pending: i64,
+}
+
+/// Every list, the default list first.
+#[tracing::instrument(name = "Get mailing lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT
+            l.list_id,
+            l.name,
+            l.is_default,
+            COUNT(*) FILTER (WHERE m.status = 'confirmed') as "n_confirmed!",
+            COUNT(*) FILTER (WHERE m.status = 'pending_confirmation') as "n_pending!"
+        FROM lists l
+        LEFT JOIN list_memberships m USING (list_id)
+        GROUP BY l.list_id
+        ORDER BY l.is_default DESC, l.name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get mailing lists.")?;
+    Ok(lists)
+}
+
+/// The list with the given id, or the default list if `list_id` is `None`.
+#[tracing::instrument(name = "Get a mailing list", skip(pool))]
+pub async fn get_list(
+    pool: &PgPool,
+    list_id: Option<Uuid>,
+) -> Result<Option<MailingList>, anyhow::Error> {
+    let list = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT
+            l.list_id,
+            l.name,
+            l.is_default,
+            COUNT(*) FILTER (WHERE m.status = 'confirmed') as "n_confirmed!",
+            COUNT(*) FILTER (WHERE m.status = 'pending_confirmation') as "n_pending!"
+        FROM lists l
+        LEFT JOIN list_memberships m USING (list_id)
+        WHERE ($1::uuid IS NULL AND l.is_default) OR l.list_id = $1
+        GROUP BY l.list_id
+        "#,
+        list_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("A database error was encountered while trying to get a mailing list.")?;
+    Ok(list)
+}
+
+/// `false` if there already is a list with this name.
+#[tracing::instrument(name = "Insert a mailing list", skip(pool))]
+pub async fn insert_list(pool: &PgPool, name: &str) -> Result<bool, anyhow::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, name, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name
+    )
+    .execute(pool)
+    .await
+    .context("A database error was encountered while trying to insert a mailing list.")?
+    .rows_affected();
+    Ok(inserted == 1)
+}
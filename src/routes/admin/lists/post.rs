@@ -0,0 +1,35 @@
+use super::persistence::insert_list;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(form, pool))]
+pub async fn create_mailing_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("Enter a name for the list.").send();
+    } else if insert_list(&pool, name).await.map_err(e500)? {
+        FlashMessage::info(format!(
+            "The list \"{}\" has been created.",
+            encode_minimal(name)
+        ))
+        .send();
+    } else {
+        FlashMessage::error(format!(
+            "There already is a list named \"{}\".",
+            encode_minimal(name)
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/lists"))
+}
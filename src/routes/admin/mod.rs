@@ -1,9 +1,17 @@
 mod admin_dashboard;
+mod dev_outbox;
+mod failed_deliveries;
+mod lists;
 mod logout;
 mod password;
+mod subscribers;
 mod newsletters;
 
 pub use admin_dashboard::admin_dashboard;
+pub use dev_outbox::*;
+pub use failed_deliveries::*;
+pub use lists::*;
 pub use logout::log_out;
 pub use password::*;
+pub use subscribers::*;
 pub use newsletters::*;
@@ -0,0 +1,45 @@
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    hidden_from_archive: bool,
+}
+
+#[tracing::instrument(name = "Change the archive visibility of a newsletter issue", skip(form, pool))]
+pub async fn set_archive_visibility(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let rows_affected = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            hidden_from_archive = $2,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        form.hidden_from_archive
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the archive visibility of the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if rows_affected == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    if form.hidden_from_archive {
+        FlashMessage::info("The newsletter issue has been hidden from the archive.").send();
+    } else {
+        FlashMessage::info("The newsletter issue is now visible in the archive.").send();
+    }
+    Ok(see_other(&format!("/admin/newsletters/{}", issue_id)))
+}
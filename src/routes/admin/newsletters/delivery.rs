@@ -0,0 +1,230 @@
+use crate::authentication::UserId;
+use crate::utils::{e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// What an admin did to the delivery of an issue, as recorded in its audit
+/// log.
+#[derive(Debug, Clone, Copy)]
+pub enum IssueAction {
+    Paused,
+    Resumed,
+    Cancelled,
+}
+
+impl IssueAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueAction::Paused => "paused",
+            IssueAction::Resumed => "resumed",
+            IssueAction::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Stop sending an issue that is going out. Deliveries that a worker has
+/// already picked up still go out.
+#[tracing::instrument(name = "Pause the delivery of a newsletter issue", skip(pool, user_id))]
+pub async fn pause_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let user_id = user_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let rows_affected = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_paused = true
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'published' AND
+            NOT delivery_paused AND
+            is_being_delivered($1)
+        "#,
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to pause the delivery of the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if rows_affected == 0 {
+        FlashMessage::error("The newsletter issue is not being delivered.").send();
+        return Ok(see_other(&format!("/admin/newsletters/{}", issue_id)));
+    }
+    log_action(&mut transaction, issue_id, IssueAction::Paused, *user_id)
+        .await
+        .map_err(e500)?;
+    commit(transaction).await?;
+    FlashMessage::info("The delivery of the newsletter issue has been paused.").send();
+    Ok(see_other(&format!("/admin/newsletters/{}", issue_id)))
+}
+
+#[tracing::instrument(
+    name = "Resume the delivery of a newsletter issue",
+    skip(pool, user_id)
+)]
+pub async fn resume_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let user_id = user_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let rows_affected = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_paused = false
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'published' AND
+            delivery_paused
+        "#,
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to resume the delivery of the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if rows_affected == 0 {
+        FlashMessage::error("The delivery of the newsletter issue is not paused.").send();
+        return Ok(see_other(&format!("/admin/newsletters/{}", issue_id)));
+    }
+    log_action(&mut transaction, issue_id, IssueAction::Resumed, *user_id)
+        .await
+        .map_err(e500)?;
+    commit(transaction).await?;
+    FlashMessage::info("The delivery of the newsletter issue has been resumed.").send();
+    Ok(see_other(&format!("/admin/newsletters/{}", issue_id)))
+}
+
+/// Stop sending an issue for good: the recipients it has not reached yet
+/// never get it.
+#[tracing::instrument(
+    name = "Cancel the delivery of a newsletter issue",
+    skip(pool, user_id)
+)]
+pub async fn cancel_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let user_id = user_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Locking the issue first keeps the scheduler from queueing the rest of
+    // a subject line test while we empty the queue.
+    let rows_affected = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'cancelled',
+            delivery_paused = false,
+            updated_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'published' AND
+            is_being_delivered($1)
+        "#,
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to cancel the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if rows_affected == 0 {
+        FlashMessage::error("The newsletter issue is not being delivered.").send();
+        return Ok(see_other(&format!("/admin/newsletters/{}", issue_id)));
+    }
+    // Deliveries a worker is recording right now are skipped rather than
+    // waited for: the worker drops those of cancelled issues the next time
+    // it claims deliveries, and never sends them.
+    let n_cancelled = sqlx::query!(
+        r#"
+        WITH unsent AS (
+            DELETE FROM issue_delivery_queue
+            WHERE (newsletter_issue_id, subscriber_email) IN (
+                SELECT newsletter_issue_id, subscriber_email
+                FROM issue_delivery_queue
+                WHERE newsletter_issue_id = $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING subscriber_email
+        )
+        UPDATE issue_deliveries
+        SET
+            status = 'cancelled',
+            updated_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email IN (SELECT subscriber_email FROM unsent)
+        "#,
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the queued deliveries of the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    log_action(&mut transaction, issue_id, IssueAction::Cancelled, *user_id)
+        .await
+        .map_err(e500)?;
+    commit(transaction).await?;
+    FlashMessage::info(format!(
+        "The newsletter issue has been cancelled. {} queued deliveries will not be sent.",
+        n_cancelled
+    ))
+    .send();
+    Ok(see_other(&format!("/admin/newsletters/{}", issue_id)))
+}
+
+async fn commit(transaction: Transaction<'_, Postgres>) -> Result<(), actix_web::Error> {
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a newsletter issue.")
+        .map_err(e500)
+}
+
+#[tracing::instrument(skip(transaction))]
+pub async fn log_action(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    action: IssueAction,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_audit_log (newsletter_issue_id, action, user_id, occurred_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        issue_id,
+        action.as_str(),
+        user_id
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to record the action in the audit log of the newsletter issue.")?;
+    Ok(())
+}
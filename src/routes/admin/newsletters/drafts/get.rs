@@ -0,0 +1,249 @@
+use super::persistence::{get_admin, get_draft};
+use crate::authentication::UserId;
+use crate::domain::SubscriberTags;
+use crate::routes::admin::lists::get_lists;
+use crate::routes::admin::newsletters::recipients::count_recipients;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn edit_draft_form(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let draft = match get_draft(&pool, draft_id.into_inner(), *user_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if let Some(issue_id) = draft.published_issue_id {
+        return Ok(see_other(&format!("/admin/newsletters/{}", issue_id)));
+    }
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // The bodies of Markdown drafts are rendered on save: the hand-written
+    // fields are left empty rather than showing output that would be ignored.
+    let (html_content, text_content) = match &draft.markdown_content {
+        Some(_) => ("", ""),
+        None => (draft.html_content.as_str(), draft.text_content.as_str()),
+    };
+    let details_open = if html_content.is_empty() && text_content.is_empty() {
+        ""
+    } else {
+        " open"
+    };
+
+    let admin = get_admin(&pool, *user_id).await.map_err(e500)?;
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    // The default list comes first.
+    let n_recipients = match lists.first() {
+        Some(list) => count_recipients(
+            &pool,
+            list.list_id,
+            &SubscriberTags::default(),
+            &SubscriberTags::default(),
+        )
+        .await
+        .map_err(e500)?,
+        None => 0,
+    };
+    let mut list_options_html = String::new();
+    for list in &lists {
+        write!(
+            list_options_html,
+            r#"<option value="{}"{}>{} ({} subscribers)</option>"#,
+            list.list_id,
+            if list.is_default { " selected" } else { "" },
+            encode_minimal(&list.name),
+            list.n_confirmed
+        )
+        .unwrap();
+    }
+    let idempotency_key = Uuid::new_v4();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit draft</title>
+</head>
+<body>
+    {msg_html}
+    <form id="draft" action="/admin/newsletters/drafts/{draft_id}" method="post">
+        <label>Title<br>
+            <input
+                placeholder="Enter the newsletter's title"
+                name="title"
+                value="{title}"
+            >
+        </label>
+        <br>
+        <label>Content (Markdown)<br>
+            <textarea
+                placeholder="Write the newsletter in Markdown"
+                name="markdown_content"
+            >{markdown_content}</textarea>
+        </label>
+        <br>
+        <details{details_open}>
+            <summary>Write the HTML and text bodies by hand instead</summary>
+            <p>These are ignored when Markdown content is provided.</p>
+            <label>HTML content<br>
+                <textarea
+                    placeholder="Enter the html content of the newsletter"
+                    name="html_content"
+                >{html_content}</textarea>
+            </label>
+            <br>
+            <label>Text content<br>
+                <textarea
+                    placeholder="Enter the text content of the newsletter"
+                    name="text_content"
+                >{text_content}</textarea>
+            </label>
+        </details>
+        <br>
+        <button type="submit">Save draft</button>
+    </form>
+    <p id="autosave-status">Last saved at {updated_at}</p>
+    <p><a href="/admin/newsletters/drafts/{draft_id}/preview" target="_blank">Preview</a></p>
+    <form id="test-send" action="/admin/newsletters/drafts/{draft_id}/test" method="post">
+        <label>Send a test email to<br>
+            <input
+                type="email"
+                placeholder="Enter an email address"
+                name="email"
+                value="{admin_email}"
+            >
+        </label>
+        <button type="submit">Send test email</button>
+    </form>
+    <form id="publish" action="/admin/newsletters" method="post">
+        <label>List<br>
+            <select name="list_id">{list_options_html}</select>
+        </label>
+        <br>
+        <label>Only send to subscribers tagged with any of (comma-separated)<br>
+            <input type="text" name="include_tags">
+        </label>
+        <br>
+        <label>Do not send to subscribers tagged with any of (comma-separated)<br>
+            <input type="text" name="exclude_tags">
+        </label>
+        <p>This issue will go to <span id="recipient-count">{n_recipients}</span> subscribers.</p>
+        <label>Publish at (UTC, leave empty to publish now)<br>
+            <input type="datetime-local" name="publish_at">
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="track_opens">
+            Track opens
+        </label>
+        <label>
+            <input type="checkbox" name="track_clicks">
+            Track clicks
+        </label>
+        <br>
+        <details>
+            <summary>Test subject lines</summary>
+            <p>The subject lines are sent to a sample of the subscribers, and the one
+            with the best open rate goes to everybody else once the test is over.
+            Opens are tracked for the whole issue.</p>
+            <label>Subject lines, one per line<br>
+                <textarea rows="4" cols="50" name="subject_variants"></textarea>
+            </label>
+            <br>
+            <label>Sample (% of the subscribers)
+                <input type="number" name="test_sample_percent" min="1" max="99" value="20">
+            </label>
+            <label>Test duration (hours)
+                <input type="number" name="test_duration_hours" min="1" value="4">
+            </label>
+        </details>
+        <br>
+        <input hidden type="text" name="draft_id" value="{draft_id}">
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <button type="submit">Publish newsletter</button>
+    </form>
+    <form action="/admin/newsletters/drafts/{draft_id}/delete" method="post">
+        <button type="submit">Delete draft</button>
+    </form>
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+    <script>
+        const draft = document.getElementById("draft");
+        const saveStatus = document.getElementById("autosave-status");
+        let dirty = false;
+        draft.addEventListener("input", () => {{ dirty = true; }});
+
+        async function autosave() {{
+            if (!dirty) {{
+                return true;
+            }}
+            dirty = false;
+            const response = await fetch("/admin/newsletters/drafts/{draft_id}/autosave", {{
+                method: "POST",
+                body: new URLSearchParams(new FormData(draft)),
+            }});
+            if (response.ok) {{
+                saveStatus.textContent = "Saved at " + new Date().toLocaleTimeString();
+            }} else {{
+                dirty = true;
+                saveStatus.textContent = "Autosave failed";
+            }}
+            return response.ok;
+        }}
+        setInterval(autosave, 5000);
+
+        const publish = document.getElementById("publish");
+        const recipientCount = document.getElementById("recipient-count");
+        async function countRecipients() {{
+            const data = new FormData(publish);
+            const query = new URLSearchParams({{
+                list_id: data.get("list_id"),
+                include_tags: data.get("include_tags"),
+                exclude_tags: data.get("exclude_tags"),
+            }});
+            const response = await fetch("/admin/newsletters/recipients?" + query);
+            recipientCount.textContent = response.ok ? (await response.json()).count : "?";
+        }}
+        publish.addEventListener("change", countRecipients);
+
+        // Test sends and publishing start from the stored draft: make sure it
+        // is up to date.
+        for (const form of [document.getElementById("test-send"), document.getElementById("publish")]) {{
+            form.addEventListener("submit", async (event) => {{
+                event.preventDefault();
+                if (await autosave()) {{
+                    form.submit();
+                }}
+            }});
+        }}
+    </script>
+</body>
+</html>"#,
+            draft_id = draft.draft_id,
+            title = encode_minimal(&draft.title),
+            markdown_content = encode_minimal(draft.markdown_content.as_deref().unwrap_or_default()),
+            html_content = encode_minimal(html_content),
+            text_content = encode_minimal(text_content),
+            updated_at = draft.updated_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            admin_email = encode_minimal(admin.email.as_deref().unwrap_or_default()),
+        )))
+}
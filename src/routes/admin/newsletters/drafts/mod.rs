@@ -0,0 +1,10 @@
+mod get;
+pub use get::edit_draft_form;
+mod persistence;
+pub use persistence::{get_draft, get_drafts, mark_draft_published, Draft};
+mod post;
+pub use post::{autosave_draft, create_draft, delete_draft, save_draft};
+mod preview;
+pub use preview::preview_draft;
+mod test_send;
+pub use test_send::send_test_email;
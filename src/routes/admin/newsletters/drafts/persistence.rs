@@ -0,0 +1,226 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub struct Draft {
+    pub draft_id: Uuid,
+    pub title: String,
+    pub markdown_content: Option<String>,
+    pub html_content: String,
+    pub text_content: String,
+    pub updated_at: DateTime<Utc>,
+    pub published_issue_id: Option<Uuid>,
+}
+
+/// The editable part of a draft.
+pub struct DraftContent {
+    pub title: String,
+    /// `None` for drafts whose HTML and text bodies are written by hand.
+    pub markdown_content: Option<String>,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+#[tracing::instrument(name = "Get a newsletter draft", skip(pool))]
+pub async fn get_draft(
+    pool: &PgPool,
+    draft_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT
+            draft_id,
+            title,
+            markdown_content,
+            html_content,
+            text_content,
+            updated_at,
+            published_issue_id
+        FROM newsletter_drafts
+        WHERE
+            draft_id = $1 AND
+            user_id = $2
+        "#,
+        draft_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("A database error was encountered while trying to get a newsletter draft.")?;
+    Ok(draft)
+}
+
+/// Drafts that have not been published yet, most recently edited first.
+#[tracing::instrument(name = "Get newsletter drafts", skip(pool))]
+pub async fn get_drafts(pool: &PgPool, user_id: Uuid) -> Result<Vec<Draft>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT
+            draft_id,
+            title,
+            markdown_content,
+            html_content,
+            text_content,
+            updated_at,
+            published_issue_id
+        FROM newsletter_drafts
+        WHERE
+            user_id = $1 AND
+            published_issue_id IS NULL
+        ORDER BY updated_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get newsletter drafts.")?;
+    Ok(drafts)
+}
+
+#[tracing::instrument(name = "Insert a newsletter draft", skip(pool, content))]
+pub async fn insert_draft(
+    pool: &PgPool,
+    user_id: Uuid,
+    content: &DraftContent,
+) -> Result<Uuid, anyhow::Error> {
+    let draft_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            draft_id,
+            user_id,
+            title,
+            markdown_content,
+            html_content,
+            text_content,
+            created_at,
+            updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now(), now())
+        "#,
+        draft_id,
+        user_id,
+        content.title,
+        content.markdown_content,
+        content.html_content,
+        content.text_content
+    )
+    .execute(pool)
+    .await
+    .context("A database error was encountered while trying to insert a newsletter draft.")?;
+    Ok(draft_id)
+}
+
+/// Returns `false` if there is no unpublished draft with the given id.
+#[tracing::instrument(name = "Update a newsletter draft", skip(pool, content))]
+pub async fn update_draft(
+    pool: &PgPool,
+    draft_id: Uuid,
+    user_id: Uuid,
+    content: &DraftContent,
+) -> Result<bool, anyhow::Error> {
+    let rows_affected = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET
+            title = $3,
+            markdown_content = $4,
+            html_content = $5,
+            text_content = $6,
+            updated_at = now()
+        WHERE
+            draft_id = $1 AND
+            user_id = $2 AND
+            published_issue_id IS NULL
+        "#,
+        draft_id,
+        user_id,
+        content.title,
+        content.markdown_content,
+        content.html_content,
+        content.text_content
+    )
+    .execute(pool)
+    .await
+    .context("A database error was encountered while trying to update a newsletter draft.")?
+    .rows_affected();
+    Ok(rows_affected > 0)
+}
+
+#[tracing::instrument(name = "Delete a newsletter draft", skip(pool))]
+pub async fn delete_draft_by_id(
+    pool: &PgPool,
+    draft_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let rows_affected = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_drafts
+        WHERE
+            draft_id = $1 AND
+            user_id = $2
+        "#,
+        draft_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("A database error was encountered while trying to delete a newsletter draft.")?
+    .rows_affected();
+    Ok(rows_affected > 0)
+}
+
+/// Link a draft to the issue it has been published as.
+///
+/// Returns `false` if the draft has already been published: the row lock taken
+/// by the update guarantees that two concurrent publishing attempts cannot
+/// both succeed.
+#[tracing::instrument(name = "Mark a newsletter draft as published", skip(transaction))]
+pub async fn mark_draft_published(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    newsletter_issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let rows_affected = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET published_issue_id = $2
+        WHERE
+            draft_id = $1 AND
+            published_issue_id IS NULL
+        "#,
+        draft_id,
+        newsletter_issue_id
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok(rows_affected > 0)
+}
+
+/// The admin editing a draft, test emails go to their address by default.
+pub struct Admin {
+    pub username: String,
+    pub email: Option<String>,
+}
+
+#[tracing::instrument(name = "Get the details of an admin", skip(pool))]
+pub async fn get_admin(pool: &PgPool, user_id: Uuid) -> Result<Admin, anyhow::Error> {
+    let admin = sqlx::query_as!(
+        Admin,
+        r#"
+        SELECT username, email
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("A database error was encountered while trying to get the details of an admin.")?;
+    Ok(admin)
+}
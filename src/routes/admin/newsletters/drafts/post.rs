@@ -0,0 +1,126 @@
+use super::persistence::{delete_draft_by_id, insert_draft, update_draft, DraftContent};
+use crate::authentication::UserId;
+use crate::markdown;
+use crate::utils::{e400, e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use std::convert::{TryFrom, TryInto};
+use uuid::Uuid;
+
+/// Drafts are either written in Markdown, from which both bodies are
+/// rendered, or by providing the HTML and the text bodies directly.
+#[derive(serde::Deserialize)]
+pub struct DraftForm {
+    title: String,
+    markdown_content: Option<String>,
+    html_content: Option<String>,
+    text_content: Option<String>,
+}
+
+impl TryFrom<DraftForm> for DraftContent {
+    type Error = String;
+
+    fn try_from(form: DraftForm) -> Result<Self, Self::Error> {
+        let DraftForm {
+            title,
+            markdown_content,
+            html_content,
+            text_content,
+        } = form;
+        match markdown_content.filter(|m| !m.trim().is_empty()) {
+            Some(markdown_content) => Ok(Self {
+                title,
+                html_content: markdown::to_html(&markdown_content),
+                text_content: markdown::to_text(&markdown_content),
+                markdown_content: Some(markdown_content),
+            }),
+            None => match (html_content, text_content) {
+                (Some(html_content), Some(text_content)) => Ok(Self {
+                    title,
+                    markdown_content: None,
+                    html_content,
+                    text_content,
+                }),
+                _ => Err("A draft needs either Markdown content or both HTML and text content."
+                    .to_string()),
+            },
+        }
+    }
+}
+
+#[tracing::instrument(name = "Create a newsletter draft", skip(form, pool))]
+pub async fn create_draft(
+    form: web::Form<DraftForm>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let content: DraftContent = form.0.try_into().map_err(e400)?;
+    let draft_id = insert_draft(&pool, *user_id, &content)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("Your draft has been saved.").send();
+    Ok(see_other(&format!("/admin/newsletters/drafts/{}", draft_id)))
+}
+
+#[tracing::instrument(name = "Save a newsletter draft", skip(form, pool))]
+pub async fn save_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftForm>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let draft_id = draft_id.into_inner();
+    let content: DraftContent = form.0.try_into().map_err(e400)?;
+    if update_draft(&pool, draft_id, *user_id, &content)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("Your draft has been saved.").send();
+        Ok(see_other(&format!("/admin/newsletters/drafts/{}", draft_id)))
+    } else {
+        FlashMessage::error("The draft does not exist or has already been published.").send();
+        Ok(see_other("/admin/newsletters"))
+    }
+}
+
+/// Called in the background by the draft editor, it does not redirect.
+#[tracing::instrument(name = "Autosave a newsletter draft", skip(form, pool))]
+pub async fn autosave_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftForm>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let content: DraftContent = form.0.try_into().map_err(e400)?;
+    if update_draft(&pool, draft_id.into_inner(), *user_id, &content)
+        .await
+        .map_err(e500)?
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+#[tracing::instrument(name = "Delete a newsletter draft", skip(pool))]
+pub async fn delete_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    if delete_draft_by_id(&pool, draft_id.into_inner(), *user_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("Your draft has been deleted.").send();
+    } else {
+        FlashMessage::error("The draft does not exist.").send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
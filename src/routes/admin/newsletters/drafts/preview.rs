@@ -0,0 +1,90 @@
+use super::persistence::{get_admin, get_draft};
+use crate::authentication::UserId;
+use crate::domain::{IssueSlug, SubscriberEmail};
+use crate::issue_delivery_worker::{render_message, IssueContent, MessageRecipient};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The address drafts are previewed for when the admin has none.
+const SAMPLE_EMAIL: &str = "subscriber@example.com";
+
+/// Show both bodies of a draft the way an email client would: the HTML body
+/// is rendered in a sandboxed frame so it cannot interfere with the admin page.
+///
+/// Bodies are rendered for the admin as if they were a subscriber, like a
+/// test email: template variables are filled in, nothing is tracked.
+pub async fn preview_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let draft = match get_draft(&pool, draft_id.into_inner(), *user_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let admin = get_admin(&pool, *user_id).await.map_err(e500)?;
+    let email = admin
+        .email
+        .and_then(|email| SubscriberEmail::parse(email).ok())
+        .unwrap_or_else(|| SubscriberEmail::parse(SAMPLE_EMAIL.into()).unwrap());
+    let slug = IssueSlug::from_title(&draft.title);
+    let issue = IssueContent {
+        newsletter_issue_id: Uuid::nil(),
+        list_id: Uuid::nil(),
+        slug: slug.as_ref(),
+        html_content: &draft.html_content,
+        text_content: &draft.text_content,
+    };
+    let recipient = MessageRecipient {
+        subscriber_id: None,
+        name: &admin.username,
+        email: &email,
+        subscription_token: None,
+    };
+    let bodies = match render_message(&issue, &recipient, None, &base_url.0, &hmac_secret.0) {
+        Ok(message) => format!(
+            r#"<h2>HTML</h2>
+    <iframe sandbox="" width="100%" height="600" srcdoc="{html_content}"></iframe>
+    <h2>Text</h2>
+    <pre>{text_content}</pre>"#,
+            html_content = encode_attribute(&message.html_content),
+            text_content = encode_minimal(&message.text_content),
+        ),
+        Err(e) => format!("<p><i>{}</i></p>", encode_minimal(&e)),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Preview</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p>As {name} &lt;{email}&gt; would receive it.</p>
+    {bodies}
+    <p><a href="/admin/newsletters/drafts/{draft_id}">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = encode_minimal(&draft.title),
+            name = encode_minimal(&admin.username),
+            email = encode_minimal(email.as_ref()),
+            draft_id = draft.draft_id,
+        )))
+}
@@ -0,0 +1,116 @@
+use super::persistence::{get_admin, get_draft};
+use crate::authentication::UserId;
+use crate::domain::{IssueSlug, SubscriberEmail};
+use crate::email_client::EmailTransport;
+use crate::issue_delivery_worker::{render_message, IssueContent, MessageRecipient};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::{e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: Option<String>,
+}
+
+/// Send the current version of a draft to a single address, rendered the same
+/// way it would be for a subscriber.
+///
+/// Nothing is stored: no issue, no delivery, no idempotency record.
+#[tracing::instrument(
+    name = "Send a test email for a newsletter draft",
+    skip(form, pool, email_client, base_url, hmac_secret)
+)]
+pub async fn send_test_email(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailTransport>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let draft_id = draft_id.into_inner();
+    let draft = match get_draft(&pool, draft_id, *user_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let redirect = see_other(&format!("/admin/newsletters/drafts/{}", draft_id));
+
+    let admin = get_admin(&pool, *user_id).await.map_err(e500)?;
+    let email = match form.0.email.filter(|e| !e.trim().is_empty()).or(admin.email) {
+        Some(email) => email,
+        None => {
+            FlashMessage::error("Enter the address to send the test email to.").send();
+            return Ok(redirect);
+        }
+    };
+    let email = match SubscriberEmail::parse(email.trim().to_string()) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(redirect);
+        }
+    };
+    // The test recipient is not a subscriber and the issue does not exist
+    // yet: links point to where they will once the issue is out, and the
+    // one-click unsubscribe URL takes nobody off any list.
+    let slug = IssueSlug::from_title(&draft.title);
+    let issue = IssueContent {
+        newsletter_issue_id: Uuid::nil(),
+        list_id: Uuid::nil(),
+        slug: slug.as_ref(),
+        html_content: &draft.html_content,
+        text_content: &draft.text_content,
+    };
+    let recipient = MessageRecipient {
+        subscriber_id: Some(Uuid::nil()),
+        name: &admin.username,
+        email: &email,
+        subscription_token: None,
+    };
+    // Nothing records opens and clicks of test emails: they are not tracked.
+    let message = match render_message(&issue, &recipient, None, &base_url.0, &hmac_secret.0) {
+        Ok(message) => message,
+        Err(e) => {
+            FlashMessage::error(format!(
+                "The test email has not been sent. {}",
+                encode_minimal(&e)
+            ))
+            .send();
+            return Ok(redirect);
+        }
+    };
+    match email_client
+        .send_email(
+            &email,
+            &draft.title,
+            &message.html_content,
+            &message.text_content,
+            &message.headers,
+        )
+        .await
+    {
+        Ok(()) => {
+            FlashMessage::info(format!(
+                "A test email has been sent to {}.",
+                encode_minimal(email.as_ref())
+            ))
+            .send();
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a test email.",
+            );
+            FlashMessage::error("The test email could not be sent, please try again.").send();
+        }
+    }
+    Ok(redirect)
+}
@@ -1,17 +1,54 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use actix_web::http::header::ContentType;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
 use std::fmt::Write;
+use uuid::Uuid;
+use crate::authentication::UserId;
+use crate::utils::e500;
+use actix_web::web::ReqData;
+use super::drafts::get_drafts;
 
 pub async fn new_newsletter_form(
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
     flash_messages: IncomingFlashMessages
 ) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
-    let idempotency_key = uuid::Uuid::new_v4();
+    let mut drafts_html = String::new();
+    for draft in get_drafts(&pool, *user_id).await.map_err(e500)? {
+        let title = if draft.title.is_empty() { "(untitled)" } else { draft.title.as_str() };
+        writeln!(
+            drafts_html,
+            r#"<li><a href="/admin/newsletters/drafts/{}">{}</a> (last edited {})</li>"#,
+            draft.draft_id,
+            encode_minimal(title),
+            draft.updated_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+
+    let mut issues_html = String::new();
+    for issue in get_recent_issues(&pool).await.map_err(e500)? {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/admin/newsletters/{}">{}</a> ({}, {})</li>"#,
+            issue.newsletter_issue_id,
+            encode_minimal(&issue.title),
+            issue.status,
+            issue.published_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -19,11 +56,12 @@ pub async fn new_newsletter_form(
 <html lang="en">
 <head>
     <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Change Password</title>
+    <title>Newsletters</title>
 </head>
 <body>
     {msg_html}
-    <form action="/admin/newsletters" method="post">
+    <h2>New draft</h2>
+    <form action="/admin/newsletters/drafts" method="post">
         <label>Title<br>
             <input
                 placeholder="Enter the newsletter's title"
@@ -31,25 +69,67 @@ pub async fn new_newsletter_form(
             >
         </label>
         <br>
-        <label>HTML content<br>
+        <label>Content (Markdown)<br>
             <textarea
-                placeholder="Enter the html content of the newsletter"
-                name="html_content"
+                placeholder="Write the newsletter in Markdown"
+                name="markdown_content"
             ></textarea>
         </label>
         <br>
-        <label>Text content<br>
-            <textarea
-                placeholder="Enter the text content of the newsletter"
-                name="text_content"
-            ></textarea>
-        </label>
+        <details>
+            <summary>Write the HTML and text bodies by hand instead</summary>
+            <p>These are ignored when Markdown content is provided.</p>
+            <label>HTML content<br>
+                <textarea
+                    placeholder="Enter the html content of the newsletter"
+                    name="html_content"
+                ></textarea>
+            </label>
+            <br>
+            <label>Text content<br>
+                <textarea
+                    placeholder="Enter the text content of the newsletter"
+                    name="text_content"
+                ></textarea>
+            </label>
+        </details>
         <br>
-        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
-        <button type="submit">Send newsletter</button>
+        <button type="submit">Create draft</button>
     </form>
+    <h2>Drafts</h2>
+    <ul>
+        {drafts_html}
+    </ul>
+    <h2>Recent issues</h2>
+    <ul>
+        {issues_html}
+    </ul>
     <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
 </html>"#)))
 
-}
\ No newline at end of file
+}
+
+struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get recent newsletter issues", skip(pool))]
+async fn get_recent_issues(pool: &PgPool) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT newsletter_issue_id, title, status, published_at
+        FROM newsletter_issues
+        ORDER BY published_at DESC
+        LIMIT 20
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get recent newsletter issues.")?;
+    Ok(issues)
+}
@@ -1,4 +1,21 @@
+mod archive;
+pub use archive::set_archive_visibility;
+mod delivery;
+pub use delivery::{cancel_delivery, pause_delivery, resume_delivery};
 mod get;
 pub use get::new_newsletter_form;
 mod post;
 pub use post::publish_newsletter;
+mod recipients;
+pub use recipients::recipient_count;
+mod report;
+pub use report::newsletter_issue_report;
+mod scheduled;
+pub use scheduled::{cancel_newsletter, reschedule_newsletter, scheduled_newsletters};
+mod stats;
+pub use stats::{newsletter_issue_stats, newsletter_issue_stats_csv};
+mod drafts;
+pub use drafts::{
+    autosave_draft, create_draft, delete_draft, edit_draft_form, preview_draft, save_draft,
+    send_test_email,
+};
@@ -1,101 +1,424 @@
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
-
 use actix_web::{web, HttpResponse};
 use anyhow::Context;
 
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use crate::utils::{see_other, e500, e400};
 use actix_web_flash_messages::FlashMessage;
-use crate::idempotency::{IdempotencyKey, get_saved_response, save_response};
+use crate::idempotency::{IdempotencyKey, NextAction, save_response, try_processing};
+use crate::issue_delivery_worker::enqueue_delivery_tasks;
 use std::convert::TryInto;
 use actix_web::web::ReqData;
 use crate::authentication::UserId;
+use crate::domain::{IssueSlug, SubscriberTags};
+use crate::routes::admin::lists::get_list;
+use crate::template::IssueTemplates;
+use crate::tracking::tracked_links;
+use htmlescape::encode_minimal;
+use super::drafts::{get_draft, mark_draft_published, Draft};
+use super::scheduled::parse_publish_at;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
 
 #[tracing::instrument(
     name="Publish a newsletter issue"
-    skip(form, pool, email_client),
+    skip(form, pool),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     user_id: ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
-    let FormData { title, text_content, html_content, idempotency_key } = form.0;
+    let FormData {
+        draft_id,
+        idempotency_key,
+        list_id,
+        include_tags,
+        exclude_tags,
+        publish_at,
+        track_opens,
+        track_clicks,
+        subject_variants,
+        test_sample_percent,
+        test_duration_hours,
+    } = form.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    // Issues scheduled in the past go out straight away.
+    let publish_at = match publish_at.filter(|p| !p.is_empty()) {
+        Some(p) => Some(parse_publish_at(&p).map_err(e400)?).filter(|p| *p > Utc::now()),
+        None => None,
+    };
+
+    let draft = match get_draft(&pool, draft_id, *user_id).await.map_err(e500)? {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
 
-    if let Some(saved_response) = get_saved_response(&pool, &idempotency_key, *user_id)
+    // The issue, its delivery tasks and the idempotency record are committed
+    // together: either the whole issue is queued for delivery or nothing is,
+    // and a retry of a request that did not get to the end starts afresh.
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
         .await
         .map_err(e500)?
     {
-        FlashMessage::info(format!("Newsletter \"{}\" has been published.", title)).send();
-        return Ok(saved_response);
+        NextAction::StartProcessing(transaction) => transaction,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message(&draft.title, publish_at).send();
+            return Ok(saved_response);
+        }
+    };
+    if draft.published_issue_id.is_some() {
+        return Ok(already_published());
+    }
+    // Both bodies are rendered for each recipient at delivery time: make sure
+    // they will render before anything is queued.
+    if let Err(e) = IssueTemplates::parse(&draft.html_content, &draft.text_content) {
+        return Ok(not_published(draft_id, &encode_minimal(&e)));
     }
+    let list = match get_list(&pool, list_id).await.map_err(e500)? {
+        Some(list) => list,
+        None => return Ok(not_published(draft_id, "There is no such list.")),
+    };
+    let audience = match (
+        SubscriberTags::parse(include_tags.as_deref().unwrap_or_default()),
+        SubscriberTags::parse(exclude_tags.as_deref().unwrap_or_default()),
+    ) {
+        (Ok(include_tags), Ok(exclude_tags)) => Audience {
+            list_id: list.list_id,
+            include_tags,
+            exclude_tags,
+        },
+        (Err(e), _) | (_, Err(e)) => return Ok(not_published(draft_id, &encode_minimal(&e))),
+    };
+    let subject_test = match SubjectTest::parse(
+        subject_variants.as_deref(),
+        test_sample_percent.as_deref(),
+        test_duration_hours.as_deref(),
+    ) {
+        Ok(subject_test) => subject_test,
+        Err(e) => return Ok(not_published(draft_id, &e)),
+    };
 
-    let subscribers = get_confirmed_subscribers(&pool).await.map_err(e500)?;
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(&subscriber.email, &title, &html_content, &text_content)
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })
-                    .map_err(e500)?;
-            }
-            Err(error) => {
-                tracing::warn!(
-                    error.cause_chain = ?error,
-                    "Skipping a confirmed subscriber. \
-                    Their stored contact details are invalid.",
-                );
-            }
-        }
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &draft,
+        &audience,
+        publish_at,
+        // Subject lines are compared by their open rates.
+        track_opens.is_some() || subject_test.is_some(),
+        track_clicks.is_some(),
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    if track_clicks.is_some() {
+        insert_issue_links(&mut transaction, issue_id, &draft.html_content)
+            .await
+            .context("Failed to store the links of the newsletter issue")
+            .map_err(e500)?;
+    }
+    if let Some(subject_test) = &subject_test {
+        insert_subject_test(&mut transaction, issue_id, subject_test)
+            .await
+            .context("Failed to store the subject lines to test")
+            .map_err(e500)?;
+    }
+    // Dropping the transaction rolls back the issue we have just inserted.
+    if !mark_draft_published(&mut transaction, draft_id, issue_id)
+        .await
+        .context("Failed to mark the draft as published")
+        .map_err(e500)?
+    {
+        return Ok(already_published());
+    }
+    // Scheduled issues are enqueued by the scheduler once they are due.
+    if publish_at.is_none() {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
     }
 
-    FlashMessage::info(format!("Newsletter \"{}\" has been published.", title)).send();
     let response = see_other("/admin/newsletters");
-    let response = save_response(&pool, &idempotency_key, *user_id, response)
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
         .await
         .map_err(e500)?;
+    success_message(&draft.title, publish_at).send();
     Ok(response)
 }
 
+/// Back to the draft page, telling the admin what to fix.
+fn not_published(draft_id: Uuid, reason: &str) -> HttpResponse {
+    FlashMessage::error(format!("The newsletter has not been published. {}", reason)).send();
+    see_other(&format!("/admin/newsletters/drafts/{}", draft_id))
+}
+
+fn already_published() -> HttpResponse {
+    FlashMessage::error("This draft has already been published.").send();
+    see_other("/admin/newsletters")
+}
+
+fn success_message(title: &str, publish_at: Option<DateTime<Utc>>) -> FlashMessage {
+    match publish_at {
+        Some(publish_at) => FlashMessage::info(format!(
+            "Newsletter \"{}\" has been scheduled for {}.",
+            title,
+            publish_at.format("%Y-%m-%d %H:%M UTC")
+        )),
+        None => FlashMessage::info(format!("Newsletter \"{}\" has been published.", title)),
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
-    title: String,
-    html_content: String,
-    text_content: String,
-    idempotency_key: String
+    draft_id: Uuid,
+    idempotency_key: String,
+    /// The default list if missing.
+    list_id: Option<Uuid>,
+    /// Comma-separated: the issue goes to the members of the list with at
+    /// least one of `include_tags` (everybody if empty) and none of
+    /// `exclude_tags`.
+    include_tags: Option<String>,
+    exclude_tags: Option<String>,
+    publish_at: Option<String>,
+    // Checkboxes are only sent when they are ticked.
+    track_opens: Option<String>,
+    track_clicks: Option<String>,
+    /// One subject line per line, to test them on a sample of the recipients
+    /// before sending the best one to everybody else.
+    subject_variants: Option<String>,
+    test_sample_percent: Option<String>,
+    test_duration_hours: Option<String>,
+}
+
+/// A subject line test, as entered on the draft page.
+#[derive(Debug, PartialEq)]
+struct SubjectTest {
+    subjects: Vec<String>,
+    sample_percent: i16,
+    duration_hours: i32,
+}
+
+impl SubjectTest {
+    /// `None` if no subject lines were entered: the issue goes out to
+    /// everybody with its title as subject.
+    fn parse(
+        subject_variants: Option<&str>,
+        sample_percent: Option<&str>,
+        duration_hours: Option<&str>,
+    ) -> Result<Option<Self>, String> {
+        let subjects: Vec<String> = subject_variants
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect();
+        match subjects.len() {
+            0 => return Ok(None),
+            1 => return Err("Enter at least two subject lines to test.".into()),
+            _ => {}
+        }
+        let sample_percent = sample_percent
+            .and_then(|p| p.trim().parse::<i16>().ok())
+            .filter(|p| (1..=99).contains(p))
+            .ok_or("The test sample must be between 1% and 99% of the recipients.")?;
+        let duration_hours = duration_hours
+            .and_then(|h| h.trim().parse::<i32>().ok())
+            .filter(|h| *h >= 1)
+            .ok_or("The test must last at least one hour.")?;
+        Ok(Some(Self {
+            subjects,
+            sample_percent,
+            duration_hours,
+        }))
+    }
 }
 
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
+/// Who an issue goes to.
+struct Audience {
+    list_id: Uuid,
+    include_tags: SubscriberTags,
+    exclude_tags: SubscriberTags,
 }
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
-async fn get_confirmed_subscribers(
-    pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
+/// Issues with the same title published at the same time pick the same slug:
+/// the second one waits for the first to commit, then tries the next free one.
+#[tracing::instrument(skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft: &Draft,
+    audience: &Audience,
+    publish_at: Option<DateTime<Utc>>,
+    track_opens: bool,
+    track_clicks: bool,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let status = if publish_at.is_some() { "scheduled" } else { "published" };
+    loop {
+        let slug = unique_slug(&mut *transaction, &draft.title).await?;
+        let n_inserted = sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issues (
+                newsletter_issue_id,
+                title,
+                text_content,
+                html_content,
+                published_at,
+                status,
+                slug,
+                track_opens,
+                track_clicks,
+                list_id,
+                include_tags,
+                exclude_tags
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            ON CONFLICT (slug) DO NOTHING
+            "#,
+            newsletter_issue_id,
+            draft.title,
+            draft.text_content,
+            draft.html_content,
+            publish_at.unwrap_or_else(Utc::now),
+            status,
+            slug.as_ref(),
+            track_opens,
+            track_clicks,
+            audience.list_id,
+            audience.include_tags.as_slice(),
+            audience.exclude_tags.as_slice()
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+        if n_inserted == 1 {
+            return Ok(newsletter_issue_id);
+        }
+    }
+}
+
+/// Store the links that deliveries of the issue will point to through
+/// `/t/c/`, numbered from 1.
+#[tracing::instrument(skip(transaction, html_content))]
+async fn insert_issue_links(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    html_content: &str,
+) -> Result<(), sqlx::Error> {
+    let urls = tracked_links(html_content);
+    let link_ids: Vec<i32> = (1..=urls.len() as i32).collect();
+    sqlx::query!(
         r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#
+        INSERT INTO issue_links (newsletter_issue_id, link_id, url)
+        SELECT $1, * FROM UNNEST($2::int[], $3::text[])
+        "#,
+        issue_id,
+        &link_ids[..],
+        &urls[..],
     )
-    .fetch_all(pool)
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Store the subject lines to test, numbered from 1. The sample is picked
+/// when the issue goes out.
+#[tracing::instrument(skip(transaction))]
+async fn insert_subject_test(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subject_test: &SubjectTest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subject_tests (newsletter_issue_id, sample_percent, test_duration_hours)
+        VALUES ($1, $2, $3)
+        "#,
+        issue_id,
+        subject_test.sample_percent,
+        subject_test.duration_hours,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let variant_ids: Vec<i16> = (1..=subject_test.subjects.len() as i16).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subject_variants (newsletter_issue_id, variant_id, subject)
+        SELECT $1, * FROM UNNEST($2::smallint[], $3::text[])
+        "#,
+        issue_id,
+        &variant_ids[..],
+        &subject_test.subjects[..],
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Pick the first archive slug derived from `title` that no other issue uses.
+#[tracing::instrument(skip(transaction))]
+async fn unique_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+) -> Result<IssueSlug, sqlx::Error> {
+    let base = IssueSlug::from_title(title);
+    let taken: Vec<String> = sqlx::query!(
+        r#"
+        SELECT slug
+        FROM newsletter_issues
+        WHERE slug = $1 OR slug LIKE $1 || '-%'
+        "#,
+        base.as_ref()
+    )
+    .fetch_all(transaction)
     .await?
     .into_iter()
-    .map(|r| match SubscriberEmail::parse(r.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(error) => Err(anyhow::anyhow!(error)),
-    })
+    .map(|r| r.slug)
     .collect();
+    let mut slug = base.clone();
+    let mut n = 2;
+    while taken.iter().any(|t| t == slug.as_ref()) {
+        slug = base.with_suffix(n);
+        n += 1;
+    }
+    Ok(slug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubjectTest;
+    use claim::{assert_err, assert_none};
 
-    Ok(confirmed_subscribers)
+    #[test]
+    fn blank_lines_are_ignored() {
+        let subject_test =
+            SubjectTest::parse(Some("First\r\n\r\n  Second  \n"), Some("20"), Some("4"))
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            subject_test,
+            SubjectTest {
+                subjects: vec!["First".into(), "Second".into()],
+                sample_percent: 20,
+                duration_hours: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn no_subject_lines_means_no_test() {
+        assert_none!(SubjectTest::parse(None, None, None).unwrap());
+        assert_none!(SubjectTest::parse(Some(" \n"), Some(""), Some("")).unwrap());
+    }
+
+    #[test]
+    fn tests_need_two_subject_lines_a_sample_and_a_duration() {
+        assert_err!(SubjectTest::parse(Some("Only one"), Some("20"), Some("4")));
+        assert_err!(SubjectTest::parse(Some("A\nB"), Some("100"), Some("4")));
+        assert_err!(SubjectTest::parse(Some("A\nB"), Some("0"), Some("4")));
+        assert_err!(SubjectTest::parse(Some("A\nB"), Some("20"), Some("0")));
+        assert_err!(SubjectTest::parse(Some("A\nB"), Some("20"), None));
+    }
 }
@@ -0,0 +1,64 @@
+use crate::domain::SubscriberTags;
+use crate::routes::admin::lists::get_list;
+use crate::utils::{e400, e500};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    list_id: Option<Uuid>,
+    include_tags: Option<String>,
+    exclude_tags: Option<String>,
+}
+
+/// How many subscribers an issue would go to, for the draft page to show
+/// while the admin picks a list and tag filters.
+#[tracing::instrument(name = "Count the recipients of an issue", skip(query, pool))]
+pub async fn recipient_count(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let QueryParams {
+        list_id,
+        include_tags,
+        exclude_tags,
+    } = query.into_inner();
+    let include_tags =
+        SubscriberTags::parse(include_tags.as_deref().unwrap_or_default()).map_err(e400)?;
+    let exclude_tags =
+        SubscriberTags::parse(exclude_tags.as_deref().unwrap_or_default()).map_err(e400)?;
+    let list = match get_list(&pool, list_id).await.map_err(e500)? {
+        Some(list) => list,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let count = count_recipients(&pool, list.list_id, &include_tags, &exclude_tags)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "count": count })))
+}
+
+/// Counted the same way as deliveries are queued, through
+/// `issue_recipients`.
+#[tracing::instrument(name = "Count recipients", skip(pool))]
+pub async fn count_recipients(
+    pool: &PgPool,
+    list_id: Uuid,
+    include_tags: &SubscriberTags,
+    exclude_tags: &SubscriberTags,
+) -> Result<i64, anyhow::Error> {
+    let count = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM issue_recipients($1, $2, $3)
+        "#,
+        list_id,
+        include_tags.as_slice(),
+        exclude_tags.as_slice()
+    )
+    .fetch_one(pool)
+    .await
+    .context("A database error was encountered while trying to count recipients.")?;
+    Ok(count.count)
+}
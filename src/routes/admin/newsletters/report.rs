@@ -0,0 +1,538 @@
+use crate::domain::DeliveryStatus;
+use crate::utils::{e400, e500};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// The recipient table is capped to keep the page responsive for large lists.
+const MAX_RECIPIENTS_SHOWN: i64 = 1000;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    status: Option<String>,
+    email: Option<String>,
+}
+
+#[tracing::instrument(name = "Show a newsletter issue delivery report", skip(query, pool))]
+pub async fn newsletter_issue_report(
+    issue_id: web::Path<Uuid>,
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let QueryParams { status, email } = query.into_inner();
+    let status = match status.filter(|s| !s.is_empty()) {
+        Some(s) => Some(DeliveryStatus::try_from(s).map_err(e400)?),
+        None => None,
+    };
+    let email = email.filter(|e| !e.trim().is_empty());
+
+    let issue = match get_issue(&pool, issue_id).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let counts = get_delivery_counts(&pool, issue_id).await.map_err(e500)?;
+    let opens_html = if issue.track_opens {
+        let opens = get_open_counts(&pool, issue_id).await.map_err(e500)?;
+        let open_rate = if opens.n_sent > 0 {
+            100.0 * opens.n_opened as f64 / opens.n_sent as f64
+        } else {
+            0.0
+        };
+        format!(
+            "<p>Opened by {} of {} recipients ({:.1}%), {} opens in total.</p>",
+            opens.n_opened, opens.n_sent, open_rate, opens.n_opens
+        )
+    } else {
+        String::from("<p>Opens are not tracked for this issue.</p>")
+    };
+    let clicks_html = if issue.track_clicks {
+        let mut rows = String::new();
+        for link in get_link_clicks(&pool, issue_id).await.map_err(e500)? {
+            writeln!(
+                rows,
+                r#"<tr><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+                encode_minimal(&link.url),
+                link.n_clickers,
+                link.n_clicks
+            )
+            .unwrap();
+        }
+        format!(
+            r#"<table>
+        <tr><th>Link</th><th>Recipients who clicked</th><th>Clicks</th></tr>
+        {rows}
+    </table>"#
+        )
+    } else {
+        String::from("<p>Clicks are not tracked for this issue.</p>")
+    };
+    let subject_test_html = match get_subject_test(&pool, issue_id).await.map_err(e500)? {
+        Some(test) => {
+            let mut rows = String::new();
+            for variant in get_subject_variants(&pool, issue_id).await.map_err(e500)? {
+                let open_rate = if variant.n_sent > 0 {
+                    100.0 * variant.n_opened as f64 / variant.n_sent as f64
+                } else {
+                    0.0
+                };
+                let winner = if test.winning_variant_id == Some(variant.variant_id) {
+                    " (winner)"
+                } else {
+                    ""
+                };
+                writeln!(
+                    rows,
+                    r#"<tr><td>{}{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>"#,
+                    encode_minimal(&variant.subject),
+                    winner,
+                    variant.n_sent,
+                    variant.n_opened,
+                    open_rate
+                )
+                .unwrap();
+            }
+            let status = match (test.winning_variant_id, test.test_ends_at) {
+                (Some(_), _) => String::from(
+                    "The test is over: the winning subject line has been sent to everybody else.",
+                ),
+                (None, Some(test_ends_at)) => format!(
+                    "Testing until {}.",
+                    test_ends_at.format("%Y-%m-%d %H:%M UTC")
+                ),
+                (None, None) => String::from("The test starts when the issue goes out."),
+            };
+            format!(
+                r#"<h2>Subject lines</h2>
+    <p>{status}</p>
+    <table>
+        <tr><th>Subject</th><th>Recipients</th><th>Opened</th><th>Open rate</th></tr>
+        {rows}
+    </table>"#
+            )
+        }
+        None => String::new(),
+    };
+    let recipients = get_recipients(&pool, issue_id, status, email.as_deref())
+        .await
+        .map_err(e500)?;
+
+    let mut counts_html = String::new();
+    let mut total = 0;
+    for s in DeliveryStatus::ALL {
+        let count = counts
+            .iter()
+            .find(|(status, _)| status == s.as_str())
+            .map(|(_, count)| *count)
+            .unwrap_or(0);
+        total += count;
+        writeln!(
+            counts_html,
+            r#"<tr><td>{}</td><td>{}</td></tr>"#,
+            s.as_str(),
+            count
+        )
+        .unwrap();
+    }
+    writeln!(counts_html, r#"<tr><td>total</td><td>{}</td></tr>"#, total).unwrap();
+
+    let mut status_options_html = String::from(r#"<option value="">any</option>"#);
+    for s in DeliveryStatus::ALL {
+        let selected = if Some(s) == status { " selected" } else { "" };
+        write!(
+            status_options_html,
+            r#"<option value="{0}"{1}>{0}</option>"#,
+            s.as_str(),
+            selected
+        )
+        .unwrap();
+    }
+
+    let mut recipients_html = String::new();
+    for recipient in &recipients {
+        writeln!(
+            recipients_html,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            encode_minimal(&recipient.subscriber_email),
+            encode_minimal(&recipient.status),
+            recipient.updated_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    let n_matching = recipients.first().map(|r| r.n_matching).unwrap_or(0);
+    let truncation_html = if n_matching > recipients.len() as i64 {
+        format!(
+            "<p>Showing the first {} of {} matching recipients.</p>",
+            recipients.len(),
+            n_matching
+        )
+    } else {
+        String::new()
+    };
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let archive_html = if issue.hidden_from_archive {
+        format!(
+            r#"<p>This issue is hidden from the public archive.</p>
+    <form action="/admin/newsletters/{issue_id}/archive" method="post">
+        <input hidden type="text" name="hidden_from_archive" value="false">
+        <button type="submit">Show in archive</button>
+    </form>"#
+        )
+    } else {
+        let link = format!("/archive/{}", encode_minimal(&issue.slug));
+        format!(
+            r#"<p>This issue is listed in the public archive at <a href="{link}">{link}</a>.</p>
+    <form action="/admin/newsletters/{issue_id}/archive" method="post">
+        <input hidden type="text" name="hidden_from_archive" value="true">
+        <button type="submit">Hide from archive</button>
+    </form>"#
+        )
+    };
+
+    let delivery_html = match (issue.status.as_str(), issue.is_being_delivered) {
+        ("cancelled", _) => String::from("<p>This issue has been cancelled.</p>"),
+        ("published", true) => {
+            let (state, toggle, label) = if issue.delivery_paused {
+                ("Delivery is paused.", "resume", "Resume")
+            } else {
+                ("This issue is being delivered.", "pause", "Pause")
+            };
+            format!(
+                r#"<p>{state}</p>
+    <form action="/admin/newsletters/{issue_id}/delivery/{toggle}" method="post">
+        <button type="submit">{label}</button>
+    </form>
+    <form action="/admin/newsletters/{issue_id}/delivery/cancel" method="post">
+        <button type="submit">Cancel the remaining deliveries</button>
+    </form>"#
+            )
+        }
+        _ => String::new(),
+    };
+    let audit_log = get_audit_log(&pool, issue_id).await.map_err(e500)?;
+    let audit_log_html = if audit_log.is_empty() {
+        String::new()
+    } else {
+        let mut rows = String::new();
+        for entry in &audit_log {
+            writeln!(
+                rows,
+                r#"<tr><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+                encode_minimal(&entry.action),
+                encode_minimal(&entry.username),
+                entry.occurred_at.to_rfc3339(),
+            )
+            .unwrap();
+        }
+        format!(
+            r#"<h2>History</h2>
+    <table>
+        <tr><th>Action</th><th>By</th><th>At</th></tr>
+        {rows}
+    </table>"#
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Delivery report</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{title}</h1>
+    <p>List: {list_name}</p>
+    {delivery_html}
+    {archive_html}
+    <table>
+        <tr><th>Status</th><th>Recipients</th></tr>
+        {counts_html}
+    </table>
+    {opens_html}
+    {clicks_html}
+    {subject_test_html}
+    <p><a href="/admin/newsletters/{issue_id}/stats">Statistics</a></p>
+    <form action="/admin/newsletters/{issue_id}" method="get">
+        <label>Status
+            <select name="status">{status_options_html}</select>
+        </label>
+        <label>Email
+            <input
+                type="text"
+                placeholder="Enter (part of) an email address"
+                name="email"
+                value="{email}"
+            >
+        </label>
+        <button type="submit">Filter</button>
+    </form>
+    <table>
+        <tr><th>Subscriber</th><th>Status</th><th>Last updated</th></tr>
+        {recipients_html}
+    </table>
+    {truncation_html}
+    {audit_log_html}
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = encode_minimal(&issue.title),
+            list_name = encode_minimal(&issue.list_name),
+            email = encode_minimal(email.as_deref().unwrap_or_default()),
+        )))
+}
+
+struct Issue {
+    title: String,
+    list_name: String,
+    slug: String,
+    status: String,
+    delivery_paused: bool,
+    is_being_delivered: bool,
+    hidden_from_archive: bool,
+    track_opens: bool,
+    track_clicks: bool,
+}
+
+#[tracing::instrument(name = "Get newsletter issue", skip(pool))]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<Option<Issue>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        Issue,
+        r#"
+        SELECT
+            i.title,
+            l.name as list_name,
+            i.slug,
+            i.status,
+            i.delivery_paused,
+            is_being_delivered(i.newsletter_issue_id) as "is_being_delivered!",
+            i.hidden_from_archive,
+            i.track_opens,
+            i.track_clicks
+        FROM newsletter_issues i
+        JOIN lists l USING (list_id)
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("A database error was encountered while trying to get a newsletter issue.")?;
+    Ok(issue)
+}
+
+#[tracing::instrument(name = "Count deliveries by status", skip(pool))]
+async fn get_delivery_counts(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<(String, i64)>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT status, COUNT(*) as "count!"
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        GROUP BY status
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to count deliveries.")?;
+    Ok(rows.into_iter().map(|r| (r.status, r.count)).collect())
+}
+
+struct OpenCounts {
+    n_sent: i64,
+    /// Recipients who opened the issue at least once.
+    n_opened: i64,
+    n_opens: i64,
+}
+
+#[tracing::instrument(name = "Count opens", skip(pool))]
+async fn get_open_counts(pool: &PgPool, issue_id: Uuid) -> Result<OpenCounts, anyhow::Error> {
+    let counts = sqlx::query_as!(
+        OpenCounts,
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE status = 'sent') as "n_sent!",
+            COUNT(first_opened_at) as "n_opened!",
+            COALESCE(SUM(n_opens), 0) as "n_opens!"
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("A database error was encountered while trying to count opens.")?;
+    Ok(counts)
+}
+
+struct LinkClicks {
+    url: String,
+    n_clickers: i64,
+    n_clicks: i64,
+}
+
+#[tracing::instrument(name = "Count clicks by link", skip(pool))]
+async fn get_link_clicks(pool: &PgPool, issue_id: Uuid) -> Result<Vec<LinkClicks>, anyhow::Error> {
+    let links = sqlx::query_as!(
+        LinkClicks,
+        r#"
+        SELECT
+            l.url,
+            COUNT(c.subscriber_email) as "n_clickers!",
+            COALESCE(SUM(c.n_clicks), 0) as "n_clicks!"
+        FROM issue_links l
+        LEFT JOIN link_clicks c USING (newsletter_issue_id, link_id)
+        WHERE l.newsletter_issue_id = $1
+        GROUP BY l.link_id, l.url
+        ORDER BY l.link_id
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to count clicks.")?;
+    Ok(links)
+}
+
+struct SubjectTest {
+    test_ends_at: Option<DateTime<Utc>>,
+    winning_variant_id: Option<i16>,
+}
+
+#[tracing::instrument(name = "Get subject line test", skip(pool))]
+async fn get_subject_test(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<SubjectTest>, anyhow::Error> {
+    let test = sqlx::query_as!(
+        SubjectTest,
+        r#"
+        SELECT test_ends_at, winning_variant_id
+        FROM subject_tests
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("A database error was encountered while trying to get a subject line test.")?;
+    Ok(test)
+}
+
+struct SubjectVariant {
+    variant_id: i16,
+    subject: String,
+    n_sent: i64,
+    n_opened: i64,
+}
+
+#[tracing::instrument(name = "Count opens by subject line", skip(pool))]
+async fn get_subject_variants(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<SubjectVariant>, anyhow::Error> {
+    let variants = sqlx::query_as!(
+        SubjectVariant,
+        r#"
+        SELECT
+            v.variant_id,
+            v.subject,
+            COUNT(*) FILTER (WHERE d.status = 'sent') as "n_sent!",
+            COUNT(d.first_opened_at) as "n_opened!"
+        FROM subject_variants v
+        LEFT JOIN issue_deliveries d
+            ON d.newsletter_issue_id = v.newsletter_issue_id AND
+               d.variant_id = v.variant_id
+        WHERE v.newsletter_issue_id = $1
+        GROUP BY v.variant_id, v.subject
+        ORDER BY v.variant_id
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to count opens by subject line.")?;
+    Ok(variants)
+}
+
+struct Recipient {
+    subscriber_email: String,
+    status: String,
+    updated_at: DateTime<Utc>,
+    /// How many recipients match the filters, including those past the limit.
+    n_matching: i64,
+}
+
+#[tracing::instrument(name = "Get newsletter issue recipients", skip(pool))]
+async fn get_recipients(
+    pool: &PgPool,
+    issue_id: Uuid,
+    status: Option<DeliveryStatus>,
+    email: Option<&str>,
+) -> Result<Vec<Recipient>, anyhow::Error> {
+    let recipients = sqlx::query_as!(
+        Recipient,
+        r#"
+        SELECT subscriber_email, status, updated_at, COUNT(*) OVER () as "n_matching!"
+        FROM issue_deliveries
+        WHERE
+            newsletter_issue_id = $1 AND
+            ($2::text IS NULL OR status = $2) AND
+            ($3::text IS NULL OR strpos(lower(subscriber_email), lower($3)) > 0)
+        ORDER BY subscriber_email
+        LIMIT $4
+        "#,
+        issue_id,
+        status.map(|s| s.as_str()),
+        email,
+        MAX_RECIPIENTS_SHOWN
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get recipients.")?;
+    Ok(recipients)
+}
+
+struct AuditLogEntry {
+    action: String,
+    username: String,
+    occurred_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get the audit log of a newsletter issue", skip(pool))]
+async fn get_audit_log(pool: &PgPool, issue_id: Uuid) -> Result<Vec<AuditLogEntry>, anyhow::Error> {
+    let entries = sqlx::query_as!(
+        AuditLogEntry,
+        r#"
+        SELECT a.action, u.username, a.occurred_at
+        FROM issue_audit_log a
+        JOIN users u USING (user_id)
+        WHERE a.newsletter_issue_id = $1
+        ORDER BY a.occurred_at
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get the audit log.")?;
+    Ok(entries)
+}
@@ -0,0 +1,222 @@
+use super::delivery::{log_action, IssueAction};
+use crate::authentication::UserId;
+use crate::utils::{e400, e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Parse the value of a `datetime-local` input. Times are interpreted as UTC.
+pub fn parse_publish_at(s: &str) -> Result<DateTime<Utc>, anyhow::Error> {
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+        .with_context(|| format!("{} is not a valid publishing time.", s))?;
+    Ok(DateTime::from_utc(naive, Utc))
+}
+
+pub async fn scheduled_newsletters(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let issues = get_scheduled_issues(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for issue in &issues {
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{title}</td>
+            <td>{publish_at}</td>
+            <td>
+                <form action="/admin/newsletters/{issue_id}/reschedule" method="post">
+                    <input type="datetime-local" name="publish_at" value="{publish_at_value}" required>
+                    <button type="submit">Reschedule</button>
+                </form>
+            </td>
+            <td>
+                <form action="/admin/newsletters/{issue_id}/cancel" method="post">
+                    <button type="submit">Cancel</button>
+                </form>
+            </td>
+        </tr>"#,
+            title = encode_minimal(&issue.title),
+            publish_at = issue.published_at.format("%Y-%m-%d %H:%M UTC"),
+            publish_at_value = issue.published_at.format("%Y-%m-%dT%H:%M"),
+            issue_id = issue.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    let body_html = if issues.is_empty() {
+        "<p>There are no scheduled newsletters.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+        <tr>
+            <th>Issue</th>
+            <th>Goes out at</th>
+            <th></th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>"#
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Scheduled newsletters</title>
+</head>
+<body>
+    {msg_html}
+    {body_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    publish_at: String,
+}
+
+#[tracing::instrument(name = "Reschedule a newsletter issue", skip(form, pool))]
+pub async fn reschedule_newsletter(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<RescheduleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let publish_at = parse_publish_at(&form.publish_at).map_err(e400)?;
+    let rows_affected = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = $2
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'scheduled'
+        "#,
+        issue_id.into_inner(),
+        publish_at
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to reschedule the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if rows_affected == 0 {
+        FlashMessage::error("The newsletter issue is no longer scheduled.").send();
+    } else {
+        FlashMessage::info(format!(
+            "The newsletter issue has been rescheduled for {}.",
+            publish_at.format("%Y-%m-%d %H:%M UTC")
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/newsletters/scheduled"))
+}
+
+#[tracing::instrument(name = "Cancel a scheduled newsletter issue", skip(pool, user_id))]
+pub async fn cancel_newsletter(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let user_id = user_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let rows_affected = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled'
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'scheduled'
+        "#,
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to cancel the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if rows_affected == 0 {
+        FlashMessage::error("The newsletter issue is no longer scheduled.").send();
+        return Ok(see_other("/admin/newsletters/scheduled"));
+    }
+    log_action(&mut transaction, issue_id, IssueAction::Cancelled, *user_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a newsletter issue.")
+        .map_err(e500)?;
+    FlashMessage::info("The newsletter issue has been cancelled.").send();
+    Ok(see_other("/admin/newsletters/scheduled"))
+}
+
+struct ScheduledIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get scheduled newsletter issues", skip(pool))]
+async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT newsletter_issue_id, title, published_at
+        FROM newsletter_issues
+        WHERE status = 'scheduled'
+        ORDER BY published_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get scheduled newsletter issues.")?;
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_publish_at;
+    use chrono::{TimeZone, Utc};
+    use claim::assert_err;
+
+    #[test]
+    fn datetime_local_values_are_parsed_as_utc() {
+        let publish_at = parse_publish_at("2022-04-18T08:00").unwrap();
+        assert_eq!(publish_at, Utc.ymd(2022, 4, 18).and_hms(8, 0, 0));
+    }
+
+    #[test]
+    fn seconds_are_optional() {
+        let publish_at = parse_publish_at("2022-04-18T08:00:30").unwrap();
+        assert_eq!(publish_at, Utc.ymd(2022, 4, 18).and_hms(8, 0, 30));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert_err!(parse_publish_at("next monday"));
+    }
+}
@@ -0,0 +1,406 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// How long after publishing opens are charted, in hours.
+const OPENS_TIME_SERIES_HOURS: i32 = 72;
+
+#[tracing::instrument(name = "Show the statistics of a newsletter issue", skip(pool))]
+pub async fn newsletter_issue_stats(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let stats = match get_issue_stats(&pool, issue_id).await.map_err(e500)? {
+        Some(stats) => stats,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let not_tracked = || String::from("<td>not tracked</td><td></td>");
+    let opened_html = if stats.track_opens {
+        format!(
+            "<td>{}</td><td>{}</td>",
+            stats.n_opened,
+            rate(stats.n_opened, stats.n_sent)
+        )
+    } else {
+        not_tracked()
+    };
+    let clicked_html = if stats.track_clicks {
+        format!(
+            "<td>{}</td><td>{}</td>",
+            stats.n_clicked,
+            rate(stats.n_clicked, stats.n_sent)
+        )
+    } else {
+        not_tracked()
+    };
+    let opens_html = if stats.track_opens {
+        let mut rows = String::new();
+        let mut total = 0;
+        for hour in get_opens_by_hour(&pool, issue_id, stats.published_at)
+            .await
+            .map_err(e500)?
+        {
+            total += hour.n_opened;
+            writeln!(
+                rows,
+                r#"<tr><td>{}-{}</td><td>{}</td><td>{}</td></tr>"#,
+                hour.hour,
+                hour.hour + 1,
+                hour.n_opened,
+                total
+            )
+            .unwrap();
+        }
+        format!(
+            r#"<p>{n_opens} opens in total.</p>
+    <table>
+        <tr><th>Hours after publishing</th><th>First opens</th><th>Opened so far</th></tr>
+        {rows}
+    </table>"#,
+            n_opens = stats.n_opens
+        )
+    } else {
+        String::from("<p>Opens are not tracked for this issue.</p>")
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Statistics</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p>Published at {published_at}.</p>
+    <table>
+        <tr><th></th><th>Recipients</th><th>Rate</th></tr>
+        <tr><td>Delivered</td><td>{n_sent}</td><td>{sent_rate}</td></tr>
+        <tr><td>Failed</td><td>{n_failed}</td><td>{failed_rate}</td></tr>
+        <tr><td>Bounced</td><td>{n_bounced}</td><td>{bounced_rate}</td></tr>
+        <tr><td>Marked as spam</td><td>{n_complained}</td><td>{complained_rate}</td></tr>
+        <tr><td>Opened</td>{opened_html}</tr>
+        <tr><td>Clicked</td>{clicked_html}</tr>
+        <tr><td>Unsubscribed</td><td>{n_unsubscribed}</td><td>{unsubscribed_rate}</td></tr>
+    </table>
+    <p>Delivery rates are relative to the {n_recipients} recipients of the issue,
+    the other rates to the {n_sent} delivered emails.</p>
+    <h2>Opens over the first {OPENS_TIME_SERIES_HOURS} hours</h2>
+    {opens_html}
+    <p><a href="/admin/newsletters/{issue_id}/stats.csv">Download as CSV</a></p>
+    <p><a href="/admin/newsletters/{issue_id}">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = encode_minimal(&stats.title),
+            published_at = stats.published_at.format("%Y-%m-%d %H:%M UTC"),
+            n_recipients = stats.n_recipients,
+            n_sent = stats.n_sent,
+            sent_rate = rate(stats.n_sent, stats.n_recipients),
+            n_failed = stats.n_failed,
+            failed_rate = rate(stats.n_failed, stats.n_recipients),
+            n_bounced = stats.n_bounced,
+            bounced_rate = rate(stats.n_bounced, stats.n_sent),
+            n_complained = stats.n_complained,
+            complained_rate = rate(stats.n_complained, stats.n_sent),
+            n_unsubscribed = stats.n_unsubscribed,
+            unsubscribed_rate = rate(stats.n_unsubscribed, stats.n_sent),
+        )))
+}
+
+/// One row per recipient, for analysis in a spreadsheet.
+#[tracing::instrument(name = "Export the statistics of a newsletter issue", skip(pool))]
+pub async fn newsletter_issue_stats_csv(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let slug = match get_slug(&pool, issue_id).await.map_err(e500)? {
+        Some(slug) => slug,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let mut csv = String::from(
+        "email,status,updated_at,first_opened_at,opens,clicks,bounced,complained,\
+        unsubscribed_at\r\n",
+    );
+    for recipient in get_recipient_stats(&pool, issue_id).await.map_err(e500)? {
+        write!(
+            csv,
+            "{},{},{},{},{},{},{},{},{}\r\n",
+            csv_field(&recipient.subscriber_email),
+            csv_field(&recipient.status),
+            recipient.updated_at.to_rfc3339(),
+            to_rfc3339(recipient.first_opened_at),
+            recipient.n_opens,
+            recipient.n_clicks,
+            recipient.bounced,
+            recipient.complained,
+            to_rfc3339(recipient.unsubscribed_at),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            format!(r#"attachment; filename="{}.csv""#, slug),
+        ))
+        .body(csv))
+}
+
+fn to_rfc3339(t: Option<DateTime<Utc>>) -> String {
+    t.map(|t| t.to_rfc3339()).unwrap_or_default()
+}
+
+fn rate(n: i64, total: i64) -> String {
+    if total > 0 {
+        format!("{:.1}%", 100.0 * n as f64 / total as f64)
+    } else {
+        String::from("-")
+    }
+}
+
+/// Quote a CSV field if needed (RFC 4180). Fields that a spreadsheet would
+/// evaluate as a formula are prefixed with a quote: email addresses come from
+/// the subscription form.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(&['=', '+', '-', '@'][..]) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains(&[',', '"', '\r', '\n'][..]) {
+        format!(r#""{}""#, value.replace('"', r#""""#))
+    } else {
+        value
+    }
+}
+
+struct IssueStats {
+    title: String,
+    published_at: DateTime<Utc>,
+    track_opens: bool,
+    track_clicks: bool,
+    n_recipients: i64,
+    n_sent: i64,
+    n_failed: i64,
+    n_bounced: i64,
+    n_complained: i64,
+    n_opened: i64,
+    n_opens: i64,
+    n_clicked: i64,
+    n_unsubscribed: i64,
+}
+
+#[tracing::instrument(name = "Get the statistics of a newsletter issue", skip(pool))]
+async fn get_issue_stats(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<IssueStats>, anyhow::Error> {
+    let stats = sqlx::query_as!(
+        IssueStats,
+        r#"
+        SELECT
+            i.title,
+            i.published_at,
+            i.track_opens,
+            i.track_clicks,
+            d.n_recipients as "n_recipients!",
+            d.n_sent as "n_sent!",
+            d.n_failed as "n_failed!",
+            d.n_bounced as "n_bounced!",
+            d.n_complained as "n_complained!",
+            d.n_opened as "n_opened!",
+            d.n_opens as "n_opens!",
+            d.n_clicked as "n_clicked!",
+            d.n_unsubscribed as "n_unsubscribed!"
+        FROM newsletter_issues i, (
+            SELECT
+                COUNT(*) as n_recipients,
+                COUNT(*) FILTER (WHERE status = 'sent') as n_sent,
+                COUNT(*) FILTER (WHERE status = 'failed') as n_failed,
+                COUNT(*) FILTER (WHERE status = 'sent' AND EXISTS (
+                    SELECT 1 FROM email_events e
+                    WHERE
+                        e.newsletter_issue_id = d.newsletter_issue_id AND
+                        e.subscriber_email = d.subscriber_email AND
+                        e.record_type = 'Bounce'
+                )) as n_bounced,
+                COUNT(*) FILTER (WHERE status = 'sent' AND EXISTS (
+                    SELECT 1 FROM email_events e
+                    WHERE
+                        e.newsletter_issue_id = d.newsletter_issue_id AND
+                        e.subscriber_email = d.subscriber_email AND
+                        e.record_type = 'SpamComplaint'
+                )) as n_complained,
+                COUNT(first_opened_at) FILTER (WHERE status = 'sent') as n_opened,
+                COALESCE(SUM(n_opens) FILTER (WHERE status = 'sent'), 0) as n_opens,
+                COUNT(*) FILTER (WHERE status = 'sent' AND EXISTS (
+                    SELECT 1 FROM link_clicks c
+                    WHERE
+                        c.newsletter_issue_id = d.newsletter_issue_id AND
+                        c.subscriber_email = d.subscriber_email
+                )) as n_clicked,
+                COUNT(*) FILTER (WHERE status = 'sent' AND EXISTS (
+                    SELECT 1 FROM issue_unsubscribes u
+                    WHERE
+                        u.newsletter_issue_id = d.newsletter_issue_id AND
+                        u.subscriber_email = d.subscriber_email
+                )) as n_unsubscribed
+            FROM issue_deliveries d
+            WHERE newsletter_issue_id = $1
+        ) d
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("A database error was encountered while trying to get issue statistics.")?;
+    Ok(stats)
+}
+
+struct OpensInHour {
+    hour: i32,
+    n_opened: i64,
+}
+
+/// How many recipients opened the issue for the first time in each hour
+/// after it was published.
+#[tracing::instrument(name = "Count opens by hour", skip(pool))]
+async fn get_opens_by_hour(
+    pool: &PgPool,
+    issue_id: Uuid,
+    published_at: DateTime<Utc>,
+) -> Result<Vec<OpensInHour>, anyhow::Error> {
+    let hours = sqlx::query_as!(
+        OpensInHour,
+        r#"
+        SELECT h.hour as "hour!", COUNT(d.first_opened_at) as "n_opened!"
+        FROM generate_series(0, $3 - 1) as h(hour)
+        LEFT JOIN issue_deliveries d
+            ON d.newsletter_issue_id = $1 AND
+               d.first_opened_at >= $2::timestamptz + make_interval(hours => h.hour) AND
+               d.first_opened_at < $2::timestamptz + make_interval(hours => h.hour + 1)
+        GROUP BY h.hour
+        ORDER BY h.hour
+        "#,
+        issue_id,
+        published_at,
+        OPENS_TIME_SERIES_HOURS
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to count opens.")?;
+    Ok(hours)
+}
+
+#[tracing::instrument(name = "Get the slug of a newsletter issue", skip(pool))]
+async fn get_slug(pool: &PgPool, issue_id: Uuid) -> Result<Option<String>, anyhow::Error> {
+    let issue = sqlx::query!(
+        r#"SELECT slug FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("A database error was encountered while trying to get a newsletter issue.")?;
+    Ok(issue.map(|i| i.slug))
+}
+
+struct RecipientStats {
+    subscriber_email: String,
+    status: String,
+    updated_at: DateTime<Utc>,
+    first_opened_at: Option<DateTime<Utc>>,
+    n_opens: i32,
+    n_clicks: i64,
+    bounced: bool,
+    complained: bool,
+    unsubscribed_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Get the statistics of every recipient", skip(pool))]
+async fn get_recipient_stats(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<RecipientStats>, anyhow::Error> {
+    let recipients = sqlx::query_as!(
+        RecipientStats,
+        r#"
+        SELECT
+            d.subscriber_email,
+            d.status,
+            d.updated_at,
+            d.first_opened_at,
+            d.n_opens,
+            (
+                SELECT COALESCE(SUM(c.n_clicks), 0)
+                FROM link_clicks c
+                WHERE
+                    c.newsletter_issue_id = $1 AND
+                    c.subscriber_email = d.subscriber_email
+            ) as "n_clicks!",
+            EXISTS (
+                SELECT 1 FROM email_events e
+                WHERE
+                    e.newsletter_issue_id = $1 AND
+                    e.subscriber_email = d.subscriber_email AND
+                    e.record_type = 'Bounce'
+            ) as "bounced!",
+            EXISTS (
+                SELECT 1 FROM email_events e
+                WHERE
+                    e.newsletter_issue_id = $1 AND
+                    e.subscriber_email = d.subscriber_email AND
+                    e.record_type = 'SpamComplaint'
+            ) as "complained!",
+            u.unsubscribed_at as "unsubscribed_at?"
+        FROM issue_deliveries d
+        LEFT JOIN issue_unsubscribes u
+            ON u.newsletter_issue_id = d.newsletter_issue_id AND
+               u.subscriber_email = d.subscriber_email
+        WHERE d.newsletter_issue_id = $1
+        ORDER BY d.subscriber_email
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get recipient statistics.")?;
+    Ok(recipients)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::csv_field;
+
+    #[test]
+    fn plain_fields_are_left_alone() {
+        assert_eq!(
+            csv_field("ursula_le_guin@gmail.com"),
+            "ursula_le_guin@gmail.com"
+        );
+    }
+
+    #[test]
+    fn fields_with_separators_or_quotes_are_quoted() {
+        assert_eq!(csv_field("a,b"), r#""a,b""#);
+        assert_eq!(csv_field(r#"say "hi""#), r#""say ""hi""""#);
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn fields_that_look_like_formulas_are_defused() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), r#""'=HYPERLINK(""x"")""#);
+        assert_eq!(csv_field("@sum"), "'@sum");
+    }
+}
@@ -0,0 +1,140 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// The subscriber table is capped to keep the page responsive for large lists.
+const MAX_SUBSCRIBERS_SHOWN: i64 = 1000;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    email: Option<String>,
+}
+
+pub async fn subscribers(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = query.into_inner().email.filter(|e| !e.trim().is_empty());
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let subscribers = get_subscribers(&pool, email.as_deref())
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for subscriber in &subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{email}</td>
+            <td>{name}</td>
+            <td>{status}</td>
+            <td>
+                <form action="/admin/subscribers/{subscriber_id}/tags" method="post">
+                    <input type="text" name="tags" value="{tags}">
+                    <button type="submit">Save tags</button>
+                </form>
+            </td>
+        </tr>"#,
+            email = encode_minimal(&subscriber.email),
+            name = encode_minimal(&subscriber.name),
+            status = encode_minimal(&subscriber.status),
+            subscriber_id = subscriber.subscriber_id,
+            tags = encode_minimal(&subscriber.tags.join(", ")),
+        )
+        .unwrap();
+    }
+    let truncation_html = if subscribers.len() as i64 >= MAX_SUBSCRIBERS_SHOWN {
+        format!(
+            "<p>Only the first {} matching subscribers are shown.</p>",
+            MAX_SUBSCRIBERS_SHOWN
+        )
+    } else {
+        String::new()
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/subscribers" method="get">
+        <label>Email
+            <input
+                type="text"
+                placeholder="Enter (part of) an email address"
+                name="email"
+                value="{email}"
+            >
+        </label>
+        <button type="submit">Filter</button>
+    </form>
+    <p>Tags are comma-separated.</p>
+    <table>
+        <tr><th>Email</th><th>Name</th><th>Status</th><th>Tags</th></tr>
+        {rows_html}
+    </table>
+    {truncation_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            email = encode_minimal(email.as_deref().unwrap_or_default()),
+        )))
+}
+
+struct Subscriber {
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    tags: Vec<String>,
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(pool))]
+async fn get_subscribers(
+    pool: &PgPool,
+    email: Option<&str>,
+) -> Result<Vec<Subscriber>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT
+            s.id as subscriber_id,
+            s.email,
+            s.name,
+            s.status,
+            COALESCE(
+                array_agg(t.tag ORDER BY t.tag) FILTER (WHERE t.tag IS NOT NULL),
+                '{}'
+            ) as "tags!"
+        FROM subscriptions s
+        LEFT JOIN subscriber_tags t ON t.subscriber_id = s.id
+        WHERE $1::text IS NULL OR strpos(lower(s.email), lower($1)) > 0
+        GROUP BY s.id
+        ORDER BY s.email
+        LIMIT $2
+        "#,
+        email,
+        MAX_SUBSCRIBERS_SHOWN
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get subscribers.")?;
+    Ok(subscribers)
+}
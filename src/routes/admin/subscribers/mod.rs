@@ -0,0 +1,4 @@
+mod get;
+pub use get::subscribers;
+mod post;
+pub use post::set_subscriber_tags;
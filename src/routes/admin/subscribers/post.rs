@@ -0,0 +1,96 @@
+use crate::domain::SubscriberTags;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    tags: String,
+}
+
+/// Replace the tags of a subscriber.
+#[tracing::instrument(name = "Set the tags of a subscriber", skip(form, pool))]
+pub async fn set_subscriber_tags(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let tags = match SubscriberTags::parse(&form.tags) {
+        Ok(tags) => tags,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    match set_tags(&pool, subscriber_id.into_inner(), &tags)
+        .await
+        .map_err(e500)?
+    {
+        Some(email) => {
+            FlashMessage::info(format!(
+                "The tags of {} have been updated.",
+                encode_minimal(&email)
+            ))
+            .send();
+            Ok(see_other("/admin/subscribers"))
+        }
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// The email of the subscriber, `None` if there is no such subscriber.
+async fn set_tags(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    tags: &SubscriberTags,
+) -> Result<Option<String>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to get the subscriber.")?;
+    let email = match subscriber {
+        Some(subscriber) => subscriber.email,
+        None => return Ok(None),
+    };
+    sqlx::query!(
+        r#"
+        DELETE FROM subscriber_tags
+        WHERE
+            subscriber_id = $1 AND
+            tag <> ALL($2)
+        "#,
+        subscriber_id,
+        tags.as_slice()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the tags of the subscriber.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, * FROM UNNEST($2::text[])
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        tags.as_slice()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to tag the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to set the tags of a subscriber.")?;
+    Ok(Some(email))
+}
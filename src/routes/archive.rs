@@ -0,0 +1,128 @@
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+struct ArchivedIssue {
+    title: String,
+    slug: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn archive(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let issues = get_archived_issues(&pool).await.map_err(e500)?;
+    let mut issues_html = String::new();
+    for issue in &issues {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/archive/{}">{}</a> ({})</li>"#,
+            encode_minimal(&issue.slug),
+            encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d"),
+        )
+        .unwrap();
+    }
+    let body_html = if issues.is_empty() {
+        "<p>No issues have been published yet.</p>".to_string()
+    } else {
+        format!("<ul>{issues_html}</ul>")
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Archive</title>
+    <link rel="alternate" type="application/atom+xml" href="/feed.atom">
+    <link rel="alternate" type="application/rss+xml" href="/feed.rss">
+</head>
+<body>
+    <h1>Past issues</h1>
+    {body_html}
+</body>
+</html>"#
+        )))
+}
+
+struct ArchivedIssueContent {
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = match get_archived_issue(&pool, &slug).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    // The HTML body is authored by the admins and is the same content that
+    // went out by email, so it is served as is.
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p><i>Published on {published_at}</i></p>
+    {html_content}
+    <p><a href="/archive">&lt;- All issues</a></p>
+</body>
+</html>"#,
+            title = encode_minimal(&issue.title),
+            published_at = issue.published_at.format("%Y-%m-%d"),
+            html_content = issue.html_content,
+        )))
+}
+
+#[tracing::instrument(name = "Get archived newsletter issues", skip(pool))]
+async fn get_archived_issues(pool: &PgPool) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT title, slug, published_at
+        FROM newsletter_issues
+        WHERE status = 'published' AND NOT hidden_from_archive
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get archived issues.")?;
+    Ok(issues)
+}
+
+#[tracing::instrument(name = "Get an archived newsletter issue", skip(pool))]
+async fn get_archived_issue(
+    pool: &PgPool,
+    slug: &str,
+) -> Result<Option<ArchivedIssueContent>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        ArchivedIssueContent,
+        r#"
+        SELECT title, html_content, published_at
+        FROM newsletter_issues
+        WHERE slug = $1 AND status = 'published' AND NOT hidden_from_archive
+        "#,
+        slug
+    )
+    .fetch_optional(pool)
+    .await
+    .context("A database error was encountered while trying to get an archived issue.")?;
+    Ok(issue)
+}
@@ -0,0 +1,201 @@
+//! Bounces and spam complaints, as reported by Postmark's webhooks.
+//!
+//! Addresses that hard bounce or complain are taken off the list: mailing
+//! them again hurts our sender reputation.
+use crate::configuration::WebhookSettings;
+use crate::routes::error_chain_fmt;
+use actix_web::http::header::{HeaderMap, HeaderValue};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+use tracing::Span;
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+pub enum EmailEvent {
+    Bounce(Bounce),
+    SpamComplaint(Bounce),
+    /// Deliveries, opens and the like: we have nothing to do with them.
+    #[serde(other)]
+    Other,
+}
+
+/// Postmark uses the same payload for bounces and spam complaints.
+#[derive(serde::Deserialize, Debug)]
+pub struct Bounce {
+    #[serde(rename = "ID")]
+    id: i64,
+    #[serde(rename = "Type")]
+    event_type: String,
+    #[serde(rename = "Email")]
+    email: String,
+    #[serde(rename = "Description")]
+    description: Option<String>,
+}
+
+impl Bounce {
+    /// Soft bounces (a full mailbox, a greylisting server, ...) are
+    /// recorded, but the address is still worth mailing.
+    fn is_hard(&self) -> bool {
+        matches!(self.event_type.as_str(), "HardBounce" | "BadEmailAddress")
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum EmailEventError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for EmailEventError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for EmailEventError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            EmailEventError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="email-events""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+            EmailEventError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Ingest an email event",
+    skip(request, event, pool, webhook),
+    fields(subscriber_email = tracing::field::Empty)
+)]
+pub async fn ingest_email_event(
+    request: HttpRequest,
+    event: web::Json<EmailEvent>,
+    pool: web::Data<PgPool>,
+    webhook: web::Data<WebhookSettings>,
+) -> Result<HttpResponse, EmailEventError> {
+    authenticate(request.headers(), &webhook).map_err(EmailEventError::AuthError)?;
+    let (record_type, bounce, new_status) = match &event.0 {
+        EmailEvent::Bounce(bounce) if bounce.is_hard() => ("Bounce", bounce, Some("bounced")),
+        EmailEvent::Bounce(bounce) => ("Bounce", bounce, None),
+        EmailEvent::SpamComplaint(complaint) => ("SpamComplaint", complaint, Some("complained")),
+        EmailEvent::Other => return Ok(HttpResponse::Ok().finish()),
+    };
+    Span::current().record("subscriber_email", &tracing::field::display(&bounce.email));
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let is_new = save_email_event(&mut transaction, record_type, bounce)
+        .await
+        .context("Failed to store the email event.")?;
+    // Providers retry webhooks: an event is only acted upon the first time.
+    if let (true, Some(new_status)) = (is_new, new_status) {
+        update_subscriber_status(&mut transaction, &bounce.email, new_status)
+            .await
+            .context("Failed to update the status of the subscriber.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the email event.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn authenticate(headers: &HeaderMap, webhook: &WebhookSettings) -> Result<(), anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::decode_config(base64encoded_segment, base64::STANDARD)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let expected = format!("{}:{}", webhook.username, webhook.password.expose_secret());
+    if bool::from(decoded_bytes.ct_eq(expected.as_bytes())) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Invalid username or password."))
+    }
+}
+
+/// Returns `false` if the event had already been received.
+///
+/// The event is attributed to the last issue delivered to the address.
+async fn save_email_event(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    record_type: &str,
+    bounce: &Bounce,
+) -> Result<bool, sqlx::Error> {
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            provider_event_id,
+            record_type,
+            event_type,
+            subscriber_email,
+            description,
+            received_at,
+            newsletter_issue_id
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, now(),
+            (
+                SELECT d.newsletter_issue_id
+                FROM issue_deliveries d
+                WHERE
+                    d.subscriber_email = $4 AND
+                    d.status = 'sent'
+                ORDER BY d.updated_at DESC
+                LIMIT 1
+            )
+        )
+        ON CONFLICT DO NOTHING
+        "#,
+        bounce.id,
+        record_type,
+        bounce.event_type,
+        bounce.email,
+        bounce.description,
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok(n_inserted > 0)
+}
+
+/// A complaint trumps a bounce: we never want to hear from that address again.
+async fn update_subscriber_status(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    email: &str,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2
+        WHERE email = $1 AND status <> 'complained'
+        "#,
+        email,
+        status,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+use actix_web::http::header::{
+    self, ETag, EntityTag, Header, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+/// How many of the latest issues the feeds carry.
+const FEED_LENGTH: i64 = 20;
+
+const FEED_TITLE: &str = "Our newsletter";
+
+#[derive(Debug, Clone, Copy)]
+enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+impl FeedFormat {
+    fn path(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "/feed.rss",
+            FeedFormat::Atom => "/feed.atom",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+        }
+    }
+}
+
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    feed(&request, &pool, &base_url.0, FeedFormat::Rss).await
+}
+
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    feed(&request, &pool, &base_url.0, FeedFormat::Atom).await
+}
+
+#[tracing::instrument(name = "Serve a feed of published issues", skip(request, pool))]
+async fn feed(
+    request: &HttpRequest,
+    pool: &PgPool,
+    base_url: &str,
+    format: FeedFormat,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Every change that shows in the feed bumps the `updated_at` of an
+    // issue: the latest one identifies the version of the feed, so that
+    // feed readers can be answered without building it.
+    let updated_at = get_feed_updated_at(pool).await.map_err(e500)?;
+    let etag = EntityTag::new_strong(format!(
+        "{}.{:06}",
+        updated_at.timestamp(),
+        updated_at.timestamp_subsec_micros()
+    ));
+    // HTTP dates have a one second precision.
+    let last_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(updated_at.timestamp() as u64);
+    if is_fresh(request, &etag, last_modified) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .insert_header(LastModified(last_modified.into()))
+            .finish());
+    }
+
+    let issues = get_feed_issues(pool).await.map_err(e500)?;
+    let body = match format {
+        FeedFormat::Rss => render_rss(&issues, updated_at, base_url),
+        FeedFormat::Atom => render_atom(&issues, updated_at, base_url),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified.into()))
+        .body(body))
+}
+
+/// Whether the copy the client already has is up to date. `If-None-Match`
+/// takes precedence over `If-Modified-Since` (RFC 7232, section 6).
+fn is_fresh(request: &HttpRequest, etag: &EntityTag, last_modified: SystemTime) -> bool {
+    if request.headers().contains_key(header::IF_NONE_MATCH) {
+        return match IfNoneMatch::parse(request) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(etags)) => etags.iter().any(|e| e.weak_eq(etag)),
+            Err(_) => false,
+        };
+    }
+    match IfModifiedSince::parse(request) {
+        Ok(IfModifiedSince(since)) => last_modified <= SystemTime::from(since),
+        Err(_) => false,
+    }
+}
+
+fn render_rss(issues: &[FeedIssue], updated_at: DateTime<Utc>, base_url: &str) -> String {
+    let mut items = String::new();
+    for issue in issues {
+        writeln!(
+            items,
+            r#"    <item>
+        <title>{title}</title>
+        <link>{base_url}/archive/{slug}</link>
+        <guid isPermaLink="false">urn:uuid:{id}</guid>
+        <pubDate>{published_at}</pubDate>
+        <description>{content}</description>
+    </item>"#,
+            title = encode_minimal(&issue.title),
+            slug = encode_minimal(&issue.slug),
+            id = issue.newsletter_issue_id,
+            published_at = issue.published_at.to_rfc2822(),
+            content = encode_minimal(&issue.html_content),
+        )
+        .unwrap();
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+    <title>{FEED_TITLE}</title>
+    <link>{base_url}/archive</link>
+    <description>Past issues of {FEED_TITLE}.</description>
+    <atom:link href="{base_url}{path}" rel="self" type="application/rss+xml"/>
+    <lastBuildDate>{updated_at}</lastBuildDate>
+{items}</channel>
+</rss>
+"#,
+        path = FeedFormat::Rss.path(),
+        updated_at = updated_at.to_rfc2822(),
+    )
+}
+
+fn render_atom(issues: &[FeedIssue], updated_at: DateTime<Utc>, base_url: &str) -> String {
+    let mut entries = String::new();
+    for issue in issues {
+        writeln!(
+            entries,
+            r#"    <entry>
+        <title>{title}</title>
+        <link href="{base_url}/archive/{slug}"/>
+        <id>urn:uuid:{id}</id>
+        <published>{published_at}</published>
+        <updated>{updated_at}</updated>
+        <content type="html">{content}</content>
+    </entry>"#,
+            title = encode_minimal(&issue.title),
+            slug = encode_minimal(&issue.slug),
+            id = issue.newsletter_issue_id,
+            published_at = issue.published_at.to_rfc3339(),
+            updated_at = issue.updated_at.to_rfc3339(),
+            content = encode_minimal(&issue.html_content),
+        )
+        .unwrap();
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+    <title>{FEED_TITLE}</title>
+    <link href="{base_url}/archive"/>
+    <link rel="self" href="{base_url}{path}"/>
+    <id>{base_url}{path}</id>
+    <author><name>{FEED_TITLE}</name></author>
+    <updated>{updated_at}</updated>
+{entries}</feed>
+"#,
+        path = FeedFormat::Atom.path(),
+        updated_at = updated_at.to_rfc3339(),
+    )
+}
+
+/// When the feeds last changed: issues leave them when they are hidden
+/// from the archive or cancelled, so those count too.
+#[tracing::instrument(name = "Get the last update of the feeds", skip(pool))]
+async fn get_feed_updated_at(pool: &PgPool) -> Result<DateTime<Utc>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COALESCE(MAX(updated_at), to_timestamp(0)) as "updated_at!"
+        FROM newsletter_issues
+        WHERE status <> 'scheduled'
+        "#
+    )
+    .fetch_one(pool)
+    .await
+    .context("A database error was encountered while trying to get the last feed update.")?;
+    Ok(row.updated_at)
+}
+
+struct FeedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    slug: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get the issues in the feeds", skip(pool))]
+async fn get_feed_issues(pool: &PgPool) -> Result<Vec<FeedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        FeedIssue,
+        r#"
+        SELECT newsletter_issue_id, title, slug, html_content, published_at, updated_at
+        FROM newsletter_issues
+        WHERE status = 'published' AND NOT hidden_from_archive
+        ORDER BY published_at DESC
+        LIMIT $1
+        "#,
+        FEED_LENGTH
+    )
+    .fetch_all(pool)
+    .await
+    .context("A database error was encountered while trying to get the issues in the feeds.")?;
+    Ok(issues)
+}
@@ -1,14 +1,24 @@
 pub use admin::*;
+pub use archive::*;
+pub use email_events::*;
+pub use feeds::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
+pub use tracking::*;
 
 mod admin;
+mod archive;
+mod email_events;
+mod feeds;
 mod health_check;
 mod home;
 mod login;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod tracking;
 
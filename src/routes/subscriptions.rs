@@ -1,5 +1,5 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTags};
+use crate::email_client::{EmailTransport, SendError};
 use crate::startup::ApplicationBaseUrl;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
@@ -15,6 +15,11 @@ use uuid::Uuid;
 pub struct FormData {
     email: String,
     name: String,
+    /// The default list if missing.
+    list_id: Option<Uuid>,
+    /// Comma-separated tags to attach to the subscriber, usually a hidden
+    /// field of the subscription form.
+    tags: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -39,9 +44,12 @@ impl TryFrom<FormData> for NewSubscriber {
 pub async fn subscribe(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailTransport>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
+    let list_id = form.list_id;
+    let tags = SubscriberTags::parse(form.tags.as_deref().unwrap_or_default())
+        .map_err(SubscribeError::ValidationError)?;
     let new_subscriber: NewSubscriber =
         form.0.try_into().map_err(SubscribeError::ValidationError)?;
     let mut transaction = db_pool
@@ -49,11 +57,16 @@ pub async fn subscribe(
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
+    let list_id = get_list_id(&mut transaction, list_id)
+        .await
+        .context("Failed to look up the list to subscribe to.")?
+        .ok_or_else(|| SubscribeError::ValidationError("There is no such list.".into()))?;
+
     let token_from_email = get_token_from_email(&mut transaction, new_subscriber.email.as_ref())
         .await
         .context("Failed to check for existing subscription tokens for the email.")?;
 
-    let subscription_token = match token_from_email {
+    let (subscriber_id, subscription_token) = match token_from_email {
         None => {
             let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
                 .await
@@ -63,10 +76,16 @@ pub async fn subscribe(
                 .await
                 .context("Failed to store the confirmation token for a new subscriber.")?;
 
-            subscription_token
+            (subscriber_id, subscription_token)
         }
-        Some(subscription_token) => subscription_token,
+        Some(existing) => existing,
     };
+    insert_membership(&mut transaction, list_id, subscriber_id)
+        .await
+        .context("Failed to add the subscriber to the list.")?;
+    add_tags(&mut transaction, subscriber_id, &tags)
+        .await
+        .context("Failed to tag the subscriber.")?;
 
     transaction
         .commit()
@@ -108,16 +127,88 @@ pub async fn insert_subscriber(
     Ok(subscriber_id)
 }
 
+/// The list to subscribe to: `list_id` if it exists, the default list if
+/// none was given.
+#[tracing::instrument(name = "Get the list to subscribe to", skip(transaction))]
+async fn get_list_id(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Option<Uuid>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT list_id
+        FROM lists
+        WHERE ($1::uuid IS NULL AND is_default) OR list_id = $1
+        "#,
+        list_id
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(result.map(|r| r.list_id))
+}
+
+/// Memberships are confirmed along with the subscriber, through the link of
+/// the confirmation email. Joining a list again after leaving it asks for a
+/// new confirmation; confirmed memberships are left alone.
+#[tracing::instrument(name = "Add a subscriber to a list", skip(transaction))]
+async fn insert_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', now())
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET
+            status = 'pending_confirmation',
+            subscribed_at = now()
+        WHERE list_memberships.status = 'unsubscribed'
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Tags are only ever added through the subscription form: subscribing again
+/// does not remove the tags the subscriber already has.
+#[tracing::instrument(name = "Tag a subscriber", skip(transaction))]
+async fn add_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    tags: &SubscriberTags,
+) -> Result<(), sqlx::Error> {
+    if tags.is_empty() {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, * FROM UNNEST($2::text[])
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        tags.as_slice()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
     skip(email_client, new_subscriber, base_url, subscription_token)
 )]
 pub async fn send_confirmation_email(
-    email_client: &EmailClient,
+    email_client: &dyn EmailTransport,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), SendError> {
     let confirmation_link = &format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
@@ -132,7 +223,13 @@ pub async fn send_confirmation_email(
         confirmation_link
     );
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", html_body, plain_body)
+        .send_email(
+            &new_subscriber.email,
+            "Welcome!",
+            html_body,
+            plain_body,
+            &[],
+        )
         .await
 }
 
@@ -216,6 +313,7 @@ pub fn error_chain_fmt(
     Ok(())
 }
 
+/// The id and subscription token of the subscriber with this email, if any.
 #[tracing::instrument(
     name = "Get the subscription_token for an email",
     skip(transaction, email)
@@ -223,9 +321,9 @@ pub fn error_chain_fmt(
 pub async fn get_token_from_email(
     transaction: &mut Transaction<'_, Postgres>,
     email: &str,
-) -> Result<Option<String>, GetExistingTokenFromEmailError> {
+) -> Result<Option<(Uuid, String)>, GetExistingTokenFromEmailError> {
     let result = sqlx::query! (
-        r#"SELECT subscription_token FROM subscription_tokens JOIN subscriptions ON subscription_tokens.subscriber_id=subscriptions.id WHERE email = $1"#,
+        r#"SELECT subscriber_id, subscription_token FROM subscription_tokens JOIN subscriptions ON subscription_tokens.subscriber_id=subscriptions.id WHERE email = $1"#,
         email,
     )
         .fetch_optional(transaction)
@@ -233,7 +331,7 @@ pub async fn get_token_from_email(
         .map_err(|e| {
             GetExistingTokenFromEmailError(e)
         })?;
-    Ok(result.map(|r| r.subscription_token))
+    Ok(result.map(|r| (r.subscriber_id, r.subscription_token)))
 }
 
 pub struct GetExistingTokenFromEmailError(sqlx::Error);
@@ -49,15 +49,40 @@ pub async fn confirm(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Confirm the address along with the lists the subscriber has asked to join.
+///
+/// Subscribers who unsubscribed confirm again after subscribing again.
+/// Addresses that bounced or complained stay as they are: an old
+/// confirmation link must not put them back on the lists.
 #[tracing::instrument(name = "Confirm a pending subscriber", skip(db_pool, subscriber_id))]
 pub async fn confirm_subscriber(db_pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut transaction = db_pool.begin().await?;
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed'WHERE id = $1"#,
+        r#"
+        UPDATE subscriptions
+        SET status = 'confirmed'
+        WHERE
+            id = $1 AND
+            status NOT IN ('bounced', 'complained')
+        "#,
         subscriber_id,
     )
-    .execute(db_pool)
+    .execute(&mut transaction)
     .await?;
-    Ok(())
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'confirmed'
+        WHERE
+            subscriber_id = $1 AND
+            status = 'pending_confirmation' AND
+            EXISTS (SELECT 1 FROM subscriptions WHERE id = $1 AND status = 'confirmed')
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await
 }
 
 #[tracing::instrument(
@@ -0,0 +1,288 @@
+use crate::list_unsubscribe::UnsubscribeTarget;
+use crate::routes::{error_chain_fmt, get_subscriber_id_from_token};
+use crate::startup::HmacSecret;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::types::Uuid;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscription_token: String,
+    /// Unsubscribe from every list if missing.
+    list_id: Option<Uuid>,
+    /// The issue whose link was followed, if any.
+    issue_id: Option<Uuid>,
+}
+
+/// The query of the URL of the `List-Unsubscribe` header.
+#[derive(serde::Deserialize)]
+pub struct OneClickParameters {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    issue_id: Uuid,
+    signature: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error("There is no such list.")]
+    UnknownList,
+    #[error("The unsubscribe link has not been signed by us.")]
+    InvalidSignature,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::UnknownToken => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnknownList => StatusCode::BAD_REQUEST,
+            UnsubscribeError::InvalidSignature => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The page behind the `{{ unsubscribe_url }}` link of newsletter issues.
+///
+/// It asks for a confirmation rather than unsubscribing straight away: links
+/// in emails are routinely fetched by scanners and previewers.
+pub async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let (list_id_html, newsletter) = match parameters.list_id {
+        Some(list_id) => {
+            let name = get_list_name(&db_pool, list_id)
+                .await
+                .context("Failed to get the name of the list.")?
+                .ok_or(UnsubscribeError::UnknownList)?;
+            (
+                format!(
+                    r#"<input hidden type="text" name="list_id" value="{}">"#,
+                    list_id
+                ),
+                encode_minimal(&name),
+            )
+        }
+        None => (String::new(), String::from("our newsletter")),
+    };
+    let issue_id_html = match parameters.issue_id {
+        Some(issue_id) => format!(
+            r#"<input hidden type="text" name="issue_id" value="{}">"#,
+            issue_id
+        ),
+        None => String::new(),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <form action="/subscriptions/unsubscribe" method="post">
+        <input hidden type="text" name="subscription_token" value="{subscription_token}">
+        {list_id_html}
+        {issue_id_html}
+        <p>You will not receive any further issues of {newsletter}.</p>
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            subscription_token = encode_minimal(&parameters.subscription_token)
+        )))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(form, db_pool))]
+pub async fn unsubscribe(
+    form: web::Form<Parameters>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscriber_id = get_subscriber_id_from_token(&db_pool, &form.subscription_token)
+        .await
+        .context("Failed to get subscriber id from the token.")?
+        .ok_or(UnsubscribeError::UnknownToken)?;
+
+    let newsletter = match form.list_id {
+        Some(list_id) => {
+            let name = get_list_name(&db_pool, list_id)
+                .await
+                .context("Failed to get the name of the list.")?
+                .ok_or(UnsubscribeError::UnknownList)?;
+            leave_list(&db_pool, subscriber_id, list_id)
+                .await
+                .context("Failed to update the membership status to `unsubscribed`.")?;
+            encode_minimal(&name)
+        }
+        None => {
+            unsubscribe_subscriber(&db_pool, subscriber_id)
+                .await
+                .context("Failed to update the subscribers status to `unsubscribed`.")?;
+            String::from("our newsletter")
+        }
+    };
+    if let Some(issue_id) = form.issue_id {
+        record_issue_unsubscribe(&db_pool, issue_id, subscriber_id)
+            .await
+            .context("Failed to record the issue the subscriber unsubscribed from.")?;
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed from {newsletter}.</p>
+</body>
+</html>"#
+        )))
+}
+
+/// Unsubscribe from every list at once.
+///
+/// Addresses that bounced or complained keep their status: subscribing
+/// again would bring back an `unsubscribed` one.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(db_pool, subscriber_id))]
+async fn unsubscribe_subscriber(db_pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut transaction = db_pool.begin().await?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE
+            id = $1 AND
+            status NOT IN ('bounced', 'complained')
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"UPDATE list_memberships SET status = 'unsubscribed' WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await
+}
+
+/// The endpoint behind the `List-Unsubscribe` header of newsletter issues.
+///
+/// Mailbox providers call it when the reader clicks their "Unsubscribe"
+/// button, so it unsubscribes straight away: unlike the link in the body,
+/// the URL is never fetched by scanners with a `POST`.
+///
+/// The body is not read: RFC 8058 fixes it to `List-Unsubscribe=One-Click`,
+/// but providers send it either URL-encoded or as `multipart/form-data`.
+/// The signature of the URL is what authorises the request.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber in one click",
+    skip(parameters, db_pool, hmac_secret)
+)]
+pub async fn unsubscribe_one_click(
+    parameters: web::Query<OneClickParameters>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let target = UnsubscribeTarget {
+        subscriber_id: parameters.subscriber_id,
+        list_id: parameters.list_id,
+        issue_id: parameters.issue_id,
+    };
+    if !target.verify(&parameters.signature, &hmac_secret.0) {
+        return Err(UnsubscribeError::InvalidSignature);
+    }
+    leave_list(&db_pool, target.subscriber_id, target.list_id)
+        .await
+        .context("Failed to update the membership status to `unsubscribed`.")?;
+    record_issue_unsubscribe(&db_pool, target.issue_id, target.subscriber_id)
+        .await
+        .context("Failed to record the issue the subscriber unsubscribed from.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber from a list",
+    skip(db_pool, subscriber_id)
+)]
+async fn leave_list(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'unsubscribed'
+        WHERE
+            subscriber_id = $1 AND
+            list_id = $2
+        "#,
+        subscriber_id,
+        list_id
+    )
+    .execute(db_pool)
+    .await?;
+    Ok(())
+}
+
+/// Issues that do not exist are ignored: the parameter comes from the link.
+#[tracing::instrument(
+    name = "Record the issue a subscriber unsubscribed from",
+    skip(db_pool, subscriber_id)
+)]
+async fn record_issue_unsubscribe(
+    db_pool: &PgPool,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_unsubscribes (
+            newsletter_issue_id,
+            subscriber_email,
+            unsubscribed_at
+        )
+        SELECT i.newsletter_issue_id, s.email, now()
+        FROM newsletter_issues i, subscriptions s
+        WHERE
+            i.newsletter_issue_id = $1 AND
+            s.id = $2
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        subscriber_id
+    )
+    .execute(db_pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get the name of a list", skip(db_pool))]
+async fn get_list_name(db_pool: &PgPool, list_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    let result = sqlx::query!(r#"SELECT name FROM lists WHERE list_id = $1"#, list_id)
+        .fetch_optional(db_pool)
+        .await?;
+    Ok(result.map(|r| r.name))
+}
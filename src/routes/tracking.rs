@@ -0,0 +1,113 @@
+use crate::tracking::{parse_click_token, TRACKING_PIXEL};
+use crate::utils::e500;
+use actix_web::http::header::{self, CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+/// Serve the tracking pixel of a delivery, recording that it was opened.
+///
+/// The pixel is served whatever the token: email clients have no use for an
+/// error, and unknown tokens must not be told apart from valid ones.
+#[tracing::instrument(name = "Track an open", skip(tracking_token, pool))]
+pub async fn track_open(
+    tracking_token: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    if let Err(e) = record_open(&pool, &tracking_token).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to record an open.",
+        );
+    }
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        // Every open should reach us, not a cache.
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoStore,
+            CacheDirective::Private,
+        ]))
+        .body(TRACKING_PIXEL)
+}
+
+async fn record_open(pool: &PgPool, tracking_token: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET
+            n_opens = n_opens + 1,
+            first_opened_at = COALESCE(first_opened_at, now())
+        WHERE tracking_token = $1
+        "#,
+        tracking_token
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Record a click on a tracked link and send the reader on to its target.
+///
+/// The target is looked up from the links stored when the issue was
+/// published: unknown tokens get a 404 rather than a redirect.
+#[tracing::instrument(name = "Track a click", skip(token, pool))]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (tracking_token, link_id) = match parse_click_token(&token) {
+        Some(parsed) => parsed,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    match record_click(&pool, tracking_token, link_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(url) => Ok(HttpResponse::Found()
+            .insert_header((header::LOCATION, url))
+            .insert_header(CacheControl(vec![
+                CacheDirective::NoStore,
+                CacheDirective::Private,
+            ]))
+            .finish()),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// Returns the target of the link, if the token points to one.
+async fn record_click(
+    pool: &PgPool,
+    tracking_token: &str,
+    link_id: i32,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH link AS (
+            SELECT d.newsletter_issue_id, d.subscriber_email, l.link_id, l.url
+            FROM issue_deliveries d
+            JOIN issue_links l ON l.newsletter_issue_id = d.newsletter_issue_id
+            WHERE
+                d.tracking_token = $1 AND
+                l.link_id = $2
+        ), click AS (
+            INSERT INTO link_clicks (
+                newsletter_issue_id,
+                link_id,
+                subscriber_email,
+                first_clicked_at,
+                n_clicks
+            )
+            SELECT newsletter_issue_id, link_id, subscriber_email, now(), 1
+            FROM link
+            ON CONFLICT (newsletter_issue_id, link_id, subscriber_email) DO UPDATE
+            SET n_clicks = link_clicks.n_clicks + 1
+        )
+        SELECT url as "url!" FROM link
+        "#,
+        tracking_token,
+        link_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| r.url))
+}
@@ -1,8 +1,20 @@
 use crate::authentication::reject_anonymous_users;
 use crate::configuration::DatabaseSettings;
-use crate::configuration::Settings;
-use crate::email_client::EmailClient;
-use crate::routes::{admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out, login, login_form, publish_newsletter, subscribe, new_newsletter_form};
+use crate::configuration::{Environment, Settings, WebhookSettings};
+use crate::email_client::{EmailTransport, Outbox};
+use crate::list_unsubscribe::ONE_CLICK_UNSUBSCRIBE_PATH;
+use crate::routes::{
+    admin_dashboard, archive, archived_issue, atom_feed, autosave_draft, cancel_delivery,
+    cancel_newsletter, change_password, change_password_form, confirm, create_draft,
+    create_mailing_list, delete_draft, dev_outbox, dev_outbox_message, dev_outbox_message_raw,
+    edit_draft_form, failed_deliveries, health_check, home, ingest_email_event, log_out, login,
+    login_form, mailing_lists, new_newsletter_form, newsletter_issue_report,
+    newsletter_issue_stats, newsletter_issue_stats_csv, pause_delivery, preview_draft,
+    publish_newsletter, recipient_count, requeue_failed_delivery, reschedule_newsletter,
+    resume_delivery, rss_feed, save_draft, scheduled_newsletters, send_test_email,
+    set_archive_visibility, set_subscriber_tags, subscribe, subscribers, track_click, track_open,
+    unsubscribe, unsubscribe_form, unsubscribe_one_click,
+};
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
@@ -16,6 +28,7 @@ use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
@@ -33,17 +46,8 @@ impl Application {
     pub async fn build(configuration: Settings) -> Result<Application, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.clone().email_client.base_url,
-            sender_email,
-            configuration.clone().email_client.authorization_token,
-            timeout,
-        );
+        let outbox = configuration.email_client.outbox();
+        let email_client = configuration.email_client.client();
 
         let address = format!(
             "{}:{}",
@@ -59,6 +63,9 @@ impl Application {
             configuration.application.base_url,
             configuration.application.hmac_secret,
             configuration.redis_uri,
+            configuration.application.environment,
+            outbox,
+            configuration.email_events_webhook,
         )
         .await?;
 
@@ -76,17 +83,26 @@ impl Application {
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct HmacSecret(pub Secret<String>);
+
+#[allow(clippy::too_many_arguments)]
 async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<dyn EmailTransport>,
     base_url: String,
     hmac_secret: Secret<String>,
     redis_uri: Secret<String>,
+    environment: Environment,
+    outbox: Option<Outbox>,
+    email_events_webhook: WebhookSettings,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = Data::new(db_pool);
-    let email_client = Data::new(email_client);
+    let email_client: Data<dyn EmailTransport> = Data::from(email_client);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let signing_secret = Data::new(HmacSecret(hmac_secret.clone()));
+    let outbox = Data::new(outbox);
+    let email_events_webhook = Data::new(email_events_webhook);
     let signing_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_framework = create_message_framework(signing_key.clone());
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
@@ -104,6 +120,22 @@ async fn run(
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                ONE_CLICK_UNSUBSCRIBE_PATH,
+                web::post().to(unsubscribe_one_click),
+            )
+            .route("/archive", web::get().to(archive))
+            .route("/archive/{slug}", web::get().to(archived_issue))
+            .route("/feed.rss", web::get().to(rss_feed))
+            .route("/feed.atom", web::get().to(atom_feed))
+            .route("/t/o/{tracking_token}.gif", web::get().to(track_open))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route("/webhooks/email-events", web::post().to(ingest_email_event))
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
@@ -112,11 +144,103 @@ async fn run(
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/newsletters", web::get().to(new_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter)),
+                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route("/newsletters/recipients", web::get().to(recipient_count))
+                    .route("/newsletters/drafts", web::post().to(create_draft))
+                    .route(
+                        "/newsletters/drafts/{draft_id}",
+                        web::get().to(edit_draft_form),
+                    )
+                    .route("/newsletters/drafts/{draft_id}", web::post().to(save_draft))
+                    .route(
+                        "/newsletters/drafts/{draft_id}/autosave",
+                        web::post().to(autosave_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/delete",
+                        web::post().to(delete_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/preview",
+                        web::get().to(preview_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/test",
+                        web::post().to(send_test_email),
+                    )
+                    .route(
+                        "/newsletters/scheduled",
+                        web::get().to(scheduled_newsletters),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/reschedule",
+                        web::post().to(reschedule_newsletter),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/cancel",
+                        web::post().to(cancel_newsletter),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/archive",
+                        web::post().to(set_archive_visibility),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/delivery/pause",
+                        web::post().to(pause_delivery),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/delivery/resume",
+                        web::post().to(resume_delivery),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/delivery/cancel",
+                        web::post().to(cancel_delivery),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/stats",
+                        web::get().to(newsletter_issue_stats),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/stats.csv",
+                        web::get().to(newsletter_issue_stats_csv),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}",
+                        web::get().to(newsletter_issue_report),
+                    )
+                    .route("/failed_deliveries", web::get().to(failed_deliveries))
+                    .route(
+                        "/failed_deliveries",
+                        web::post().to(requeue_failed_delivery),
+                    )
+                    .route("/lists", web::get().to(mailing_lists))
+                    .route("/lists", web::post().to(create_mailing_list))
+                    .route("/subscribers", web::get().to(subscribers))
+                    .route(
+                        "/subscribers/{subscriber_id}/tags",
+                        web::post().to(set_subscriber_tags),
+                    )
+                    .configure(|cfg| {
+                        // Development tools never leave a developer's machine.
+                        if environment == Environment::Local {
+                            cfg.route("/dev/outbox", web::get().to(dev_outbox))
+                                .route(
+                                    "/dev/outbox/{message_id}",
+                                    web::get().to(dev_outbox_message),
+                                )
+                                .route(
+                                    "/dev/outbox/{message_id}/raw",
+                                    web::get().to(dev_outbox_message_raw),
+                                );
+                        }
+                    }),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(signing_secret.clone())
+            .app_data(outbox.clone())
+            .app_data(email_events_webhook.clone())
     })
     .listen(listener)?
     .run();
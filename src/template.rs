@@ -0,0 +1,217 @@
+//! Per-recipient variables in the bodies of newsletter issues.
+//!
+//! Bodies may contain `{{ variable }}` tags, which are replaced with the
+//! details of each recipient when the issue is delivered.
+//! Templates are checked when an issue is published, so that an unknown
+//! variable or a dangling `{{` never reaches a subscriber's inbox.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TemplateVariable {
+    SubscriberName,
+    SubscriberEmail,
+    UnsubscribeUrl,
+    ArchiveUrl,
+}
+
+impl TemplateVariable {
+    pub const ALL: [TemplateVariable; 4] = [
+        TemplateVariable::SubscriberName,
+        TemplateVariable::SubscriberEmail,
+        TemplateVariable::UnsubscribeUrl,
+        TemplateVariable::ArchiveUrl,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TemplateVariable::SubscriberName => "subscriber.name",
+            TemplateVariable::SubscriberEmail => "subscriber.email",
+            TemplateVariable::UnsubscribeUrl => "unsubscribe_url",
+            TemplateVariable::ArchiveUrl => "archive_url",
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum TemplateError {
+    #[error("Unknown variable `{{{{ {name} }}}}` on line {line}.")]
+    UnknownVariable { name: String, line: usize },
+    #[error("A `{{{{` on line {line} is never closed with `}}}}`.")]
+    UnclosedTag { line: usize },
+}
+
+/// The values a template is rendered with for a single recipient.
+pub struct TemplateContext<'a> {
+    pub subscriber_name: &'a str,
+    pub subscriber_email: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub archive_url: &'a str,
+}
+
+impl TemplateContext<'_> {
+    fn value(&self, variable: TemplateVariable) -> &str {
+        match variable {
+            TemplateVariable::SubscriberName => self.subscriber_name,
+            TemplateVariable::SubscriberEmail => self.subscriber_email,
+            TemplateVariable::UnsubscribeUrl => self.unsubscribe_url,
+            TemplateVariable::ArchiveUrl => self.archive_url,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Text(&'a str),
+    Variable(TemplateVariable),
+}
+
+#[derive(Debug)]
+pub struct Template<'a>(Vec<Segment<'a>>);
+
+impl<'a> Template<'a> {
+    pub fn parse(source: &'a str) -> Result<Template<'a>, TemplateError> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+        while let Some(open) = rest.find("{{") {
+            let line = line_number(source, rest, open);
+            let after_open = &rest[open + 2..];
+            let close = after_open
+                .find("}}")
+                .ok_or(TemplateError::UnclosedTag { line })?;
+            let name = after_open[..close].trim();
+            let variable = TemplateVariable::ALL
+                .iter()
+                .find(|v| v.name() == name)
+                .ok_or_else(|| TemplateError::UnknownVariable {
+                    name: name.to_string(),
+                    line,
+                })?;
+            if open > 0 {
+                segments.push(Segment::Text(&rest[..open]));
+            }
+            segments.push(Segment::Variable(*variable));
+            rest = &after_open[close + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest));
+        }
+        Ok(Template(segments))
+    }
+
+    /// Render the template, passing every variable value through `escape`
+    /// (e.g. HTML escaping for the HTML body of an issue).
+    pub fn render<F>(&self, context: &TemplateContext, escape: F) -> String
+    where
+        F: Fn(&str) -> String,
+    {
+        let mut rendered = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Variable(variable) => rendered.push_str(&escape(context.value(*variable))),
+            }
+        }
+        rendered
+    }
+}
+
+/// The HTML and text bodies of an issue, as sent to each recipient.
+pub struct IssueTemplates<'a> {
+    html: Template<'a>,
+    text: Template<'a>,
+}
+
+impl<'a> IssueTemplates<'a> {
+    pub fn parse(html: &'a str, text: &'a str) -> Result<IssueTemplates<'a>, String> {
+        Ok(IssueTemplates {
+            html: Template::parse(html).map_err(|e| format!("HTML content: {}", e))?,
+            text: Template::parse(text).map_err(|e| format!("Text content: {}", e))?,
+        })
+    }
+
+    /// Returns the HTML body and the text body, in this order.
+    pub fn render(&self, context: &TemplateContext) -> (String, String) {
+        (
+            self.html.render(context, htmlescape::encode_minimal),
+            self.text.render(context, str::to_string),
+        )
+    }
+}
+
+/// The 1-based line of `source` on which `rest[offset]` lies.
+fn line_number(source: &str, rest: &str, offset: usize) -> usize {
+    let position = source.len() - rest.len() + offset;
+    source[..position].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Template, TemplateContext, TemplateError};
+    use claim::{assert_err, assert_ok};
+
+    fn context() -> TemplateContext<'static> {
+        TemplateContext {
+            subscriber_name: "Ursula <3",
+            subscriber_email: "ursula@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?token=abc",
+            archive_url: "https://example.com/archive/issue",
+        }
+    }
+
+    #[test]
+    fn text_without_tags_is_rendered_as_is() {
+        let template = Template::parse("Hello there } { }}").unwrap();
+        assert_eq!(template.render(&context(), str::to_string), "Hello there } { }}");
+    }
+
+    #[test]
+    fn variables_are_substituted() {
+        let template = Template::parse(
+            "Hi {{ subscriber.name }} ({{subscriber.email}})!\n\
+            Unsubscribe: {{  unsubscribe_url  }} - Archive: {{ archive_url }}",
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&context(), str::to_string),
+            "Hi Ursula <3 (ursula@example.com)!\n\
+            Unsubscribe: https://example.com/unsubscribe?token=abc - \
+            Archive: https://example.com/archive/issue"
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_but_the_template_is_not() {
+        let template = Template::parse("<p>Hi {{ subscriber.name }}</p>").unwrap();
+        let rendered = template.render(&context(), |v| v.replace('<', "&lt;"));
+        assert_eq!(rendered, "<p>Hi Ursula &lt;3</p>");
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        let error = Template::parse("Hello\n{{ subscriber.age }}").unwrap_err();
+        assert_eq!(
+            error,
+            TemplateError::UnknownVariable {
+                name: "subscriber.age".into(),
+                line: 2
+            }
+        );
+        assert_eq!(error.to_string(), "Unknown variable `{{ subscriber.age }}` on line 2.");
+    }
+
+    #[test]
+    fn unclosed_tags_are_rejected() {
+        let error = Template::parse("{{ archive_url }}\n\nHi {{ subscriber.name").unwrap_err();
+        assert_eq!(error, TemplateError::UnclosedTag { line: 3 });
+        assert_eq!(error.to_string(), "A `{{` on line 3 is never closed with `}}`.");
+    }
+
+    #[test]
+    fn empty_tags_are_rejected() {
+        assert_err!(Template::parse("{{}}"));
+    }
+
+    #[test]
+    fn templates_can_start_and_end_with_a_variable() {
+        assert_ok!(Template::parse("{{ subscriber.name }}"));
+    }
+}
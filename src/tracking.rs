@@ -0,0 +1,225 @@
+//! Open and click tracking for newsletter issues.
+//!
+//! Each delivery of an issue with tracking turned on gets a random token.
+//! The HTML body references it through a 1x1 image, fetching which marks the
+//! issue as opened by that recipient, and through its links, which go
+//! through `/t/c/` to record the click before redirecting.
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+/// A transparent 1x1 GIF.
+pub const TRACKING_PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Tokens are drawn from a CSPRNG and tied to nothing but the row they are
+/// stored in.
+pub fn generate_tracking_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+pub fn open_tracking_url(base_url: &str, tracking_token: &str) -> String {
+    format!("{}/t/o/{}.gif", base_url, tracking_token)
+}
+
+/// A tracked link: `link_id` identifies the target among the links of the
+/// issue, which are stored when it is published.
+pub fn click_tracking_url(base_url: &str, tracking_token: &str, link_id: i32) -> String {
+    format!("{}/t/c/{}-{}", base_url, tracking_token, link_id)
+}
+
+/// The tracking token and link id of a click tracking token, as built by
+/// [`click_tracking_url`].
+pub fn parse_click_token(token: &str) -> Option<(&str, i32)> {
+    let (tracking_token, link_id) = token.rsplit_once('-')?;
+    Some((tracking_token, link_id.parse().ok()?))
+}
+
+/// The distinct targets of the links of `html` that can be tracked, in the
+/// order they first appear.
+///
+/// Links built from template variables (e.g. `{{ unsubscribe_url }}`) differ
+/// for every recipient and are left alone, as are `mailto:` links, anchors
+/// and the like.
+pub fn tracked_links(html: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    rewrite_links(html, |url| {
+        if is_trackable(url) && !links.iter().any(|l| l == url) {
+            links.push(url.to_string());
+        }
+        None
+    });
+    links
+}
+
+fn is_trackable(url: &str) -> bool {
+    (url.starts_with("http://") || url.starts_with("https://")) && !url.contains("{{")
+}
+
+/// Call `rewrite` with the (decoded) target of every `<a href>` in `html`,
+/// replacing the target with the URL it returns, if any.
+pub fn rewrite_links<F>(html: &str, mut rewrite: F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    // Lowercasing ASCII keeps byte offsets as they are.
+    let lowercase = html.to_ascii_lowercase();
+    let mut rewritten = String::with_capacity(html.len());
+    let mut copied = 0;
+    let mut from = 0;
+    while let Some(offset) = lowercase[from..].find("<a") {
+        let attributes_start = from + offset + 2;
+        from = attributes_start;
+        // `<abbr>`, `<article>`, ... are not links.
+        if !lowercase[attributes_start..].starts_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let tag_end = match lowercase[attributes_start..].find('>') {
+            Some(offset) => attributes_start + offset,
+            None => break,
+        };
+        from = tag_end;
+        let (start, end) = match href_value(&lowercase[attributes_start..tag_end]) {
+            Some((start, end)) => (attributes_start + start, attributes_start + end),
+            None => continue,
+        };
+        let raw = &html[start..end];
+        let url = htmlescape::decode_html(raw).unwrap_or_else(|_| raw.to_string());
+        if let Some(new_url) = rewrite(&url) {
+            rewritten.push_str(&html[copied..start]);
+            rewritten.push_str(&htmlescape::encode_minimal(&new_url));
+            copied = end;
+        }
+    }
+    rewritten.push_str(&html[copied..]);
+    rewritten
+}
+
+/// The byte range of the value of the `href` attribute among the attributes
+/// of a tag.
+fn href_value(attributes: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some(offset) = attributes[from..].find("href") {
+        let name_start = from + offset;
+        from = name_start + 4;
+        if !attributes[..name_start].ends_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let value = match attributes[from..].trim_start().strip_prefix('=') {
+            Some(value) => value.trim_start(),
+            None => continue,
+        };
+        let value_start = attributes.len() - value.len();
+        return match value.chars().next()? {
+            quote @ ('"' | '\'') => {
+                let length = value[1..].find(quote)?;
+                Some((value_start + 1, value_start + 1 + length))
+            }
+            _ => {
+                let length = value
+                    .find(|c: char| c.is_ascii_whitespace())
+                    .unwrap_or(value.len());
+                Some((value_start, value_start + length))
+            }
+        };
+    }
+    None
+}
+
+/// Add the tracking pixel at the end of the body of `html`, or at the end of
+/// `html` if it is a fragment.
+pub fn inject_open_pixel(html: &str, pixel_url: &str) -> String {
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="border:0">"#,
+        htmlescape::encode_minimal(pixel_url)
+    );
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(position) => format!("{}{}{}", &html[..position], pixel, &html[position..]),
+        None => format!("{}{}", html, pixel),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        generate_tracking_token, inject_open_pixel, parse_click_token, rewrite_links, tracked_links,
+    };
+
+    const PIXEL: &str = r#"<img src="https://example.com/t/o/abc.gif" width="1" height="1" alt="" style="border:0">"#;
+
+    #[test]
+    fn the_pixel_goes_at_the_end_of_the_body() {
+        let html = "<html><body><p>Hi</p></BODY></html>";
+        assert_eq!(
+            inject_open_pixel(html, "https://example.com/t/o/abc.gif"),
+            format!("<html><body><p>Hi</p>{}</BODY></html>", PIXEL)
+        );
+    }
+
+    #[test]
+    fn the_pixel_is_appended_to_fragments() {
+        assert_eq!(
+            inject_open_pixel("<p>Hi</p>", "https://example.com/t/o/abc.gif"),
+            format!("<p>Hi</p>{}", PIXEL)
+        );
+    }
+
+    #[test]
+    fn links_are_rewritten_whatever_their_quoting() {
+        let html = r#"<p><A class="x" HREF = "https://a.example">a</A>
+            <a href='https://b.example/?x=1&amp;y=2'>b</a>
+            <a title="no link">c</a> <abbr href="https://d.example">d</abbr>
+            <a href=https://e.example>e</a></p>"#;
+        let mut seen = Vec::new();
+        let rewritten = rewrite_links(html, |url| {
+            seen.push(url.to_string());
+            Some(format!("https://t.example/{}", seen.len()))
+        });
+        assert_eq!(
+            seen,
+            vec![
+                "https://a.example",
+                "https://b.example/?x=1&y=2",
+                "https://e.example",
+            ]
+        );
+        assert_eq!(
+            rewritten,
+            r#"<p><A class="x" HREF = "https://t.example/1">a</A>
+            <a href='https://t.example/2'>b</a>
+            <a title="no link">c</a> <abbr href="https://d.example">d</abbr>
+            <a href=https://t.example/3>e</a></p>"#
+        );
+    }
+
+    #[test]
+    fn only_links_that_are_the_same_for_everyone_are_tracked() {
+        let html = r##"<a href="https://a.example">a</a>
+            <a href="{{ unsubscribe_url }}">Unsubscribe</a>
+            <a href="https://a.example">a again</a>
+            <a href="mailto:editor@example.com">Reply</a>
+            <a href="#top">Top</a>
+            <a href="https://b.example/?to={{ subscriber.email }}">b</a>"##;
+        assert_eq!(tracked_links(html), vec!["https://a.example"]);
+    }
+
+    #[test]
+    fn click_tokens_round_trip() {
+        assert_eq!(parse_click_token("abc123-7"), Some(("abc123", 7)));
+        assert_eq!(parse_click_token("abc123"), None);
+        assert_eq!(parse_click_token("abc123-x"), None);
+    }
+
+    #[test]
+    fn tokens_are_not_reused() {
+        let token = generate_tracking_token();
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, generate_tracking_token());
+    }
+}
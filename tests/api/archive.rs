@@ -0,0 +1,167 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_sample_draft, publish_newsletter, spawn_app, TestApp,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+async fn get_issue_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[actix_rt::test]
+async fn published_issues_are_listed_in_the_public_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    app.post_logout().await;
+
+    // Act
+    let html_page = app.get_archive_html().await;
+
+    // Assert
+    assert!(html_page.contains(r#"<a href="/archive/newsletter-title">Newsletter title</a>"#));
+    let response = app.get_archived_issue("newsletter-title").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Newsletter title</h1>"));
+    assert!(html_page.contains("<p>Newsletter body as HTML</p>"));
+}
+
+#[actix_rt::test]
+async fn issues_sharing_a_title_get_distinct_slugs() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_newsletter(&app).await;
+    publish_newsletter(&app).await;
+
+    // Assert
+    let html_page = app.get_archive_html().await;
+    assert!(html_page.contains(r#"<a href="/archive/newsletter-title">"#));
+    assert!(html_page.contains(r#"<a href="/archive/newsletter-title-2">"#));
+}
+
+#[actix_rt::test]
+async fn issues_sharing_a_title_published_at_the_same_time_get_distinct_slugs() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Another issue with the same title, being published.
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, published_at, slug, list_id
+        )
+        SELECT $1, 'Newsletter title', '', '', now(), 'newsletter-title', list_id
+        FROM lists
+        WHERE is_default
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&mut transaction)
+    .await
+    .unwrap();
+    let commit_later = async {
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        transaction.commit().await.unwrap();
+    };
+
+    // Act
+    tokio::join!(publish_newsletter(&app), commit_later);
+
+    // Assert
+    let html_page = app.get_archive_html().await;
+    assert!(html_page.contains(r#"<a href="/archive/newsletter-title">"#));
+    assert!(html_page.contains(r#"<a href="/archive/newsletter-title-2">"#));
+}
+
+#[actix_rt::test]
+async fn unknown_slugs_return_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_archived_issue("there-is-no-such-issue").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_hide_an_issue_from_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_archive_visibility(
+            Uuid::new_v4(),
+            &serde_json::json!({ "hidden_from_archive": true }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_rt::test]
+async fn hidden_issues_are_not_served_by_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let issue_id = get_issue_id(&app).await;
+
+    // Act - Hide the issue
+    let response = app
+        .post_archive_visibility(issue_id, &serde_json::json!({ "hidden_from_archive": true }))
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been hidden from the archive.</i></p>"));
+
+    // Assert
+    let html_page = app.get_archive_html().await;
+    assert!(!html_page.contains("Newsletter title"));
+    let response = app.get_archived_issue("newsletter-title").await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    // Act - Show it again
+    app.post_archive_visibility(issue_id, &serde_json::json!({ "hidden_from_archive": false }))
+        .await;
+
+    // Assert
+    let response = app.get_archived_issue("newsletter-title").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_rt::test]
+async fn scheduled_issues_are_not_archived_before_they_go_out() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_sample_draft(&app).await;
+    let publish_at = Utc::now() + Duration::days(1);
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "draft_id": draft_id,
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "publish_at": publish_at.format("%Y-%m-%dT%H:%M").to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act
+    let response = app.get_archived_issue("newsletter-title").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
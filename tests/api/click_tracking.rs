@@ -0,0 +1,113 @@
+use crate::helpers::{create_confirmed_subscriber, publish_and_deliver, spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn create_draft_with_links(app: &TestApp) -> Uuid {
+    app.create_draft(&serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": r#"<p>Read <a href="https://example.com/blog?a=1&amp;b=2">the blog</a>
+            or <a href="https://example.com/docs">the docs</a>.</p>
+            <a href="{{ unsubscribe_url }}">Unsubscribe</a>"#,
+        "text_content": "Newsletter body as plain text",
+    }))
+    .await
+}
+
+/// The targets of the links in `html`, relative to the application if they
+/// are tracked.
+fn hrefs(html: &str, address: &str) -> Vec<String> {
+    html.split(r#"href=""#)
+        .skip(1)
+        .map(|s| s[..s.find('"').unwrap()].replace(address, ""))
+        .collect()
+}
+
+#[actix_rt::test]
+async fn links_are_tracked_when_the_issue_asks_for_it() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft_with_links(&app).await;
+    let (issue_id, html_body) = publish_and_deliver(&app, draft_id, &["track_clicks"]).await;
+    let hrefs = hrefs(&html_body, &app.address);
+    assert!(hrefs[0].starts_with("/t/c/"));
+    assert!(hrefs[1].starts_with("/t/c/"));
+    assert!(
+        hrefs[2].contains("/subscriptions/unsubscribe"),
+        "Per-recipient links must not be tracked."
+    );
+
+    // Act
+    for href in [&hrefs[0], &hrefs[0], &hrefs[1]] {
+        let response = app
+            .api_client
+            .get(&format!("{}{}", app.address, href))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 302);
+    }
+
+    // Assert
+    let response = app
+        .api_client
+        .get(&format!("{}{}", app.address, hrefs[0]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/blog?a=1&b=2"
+    );
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+    assert!(html_page
+        .contains("<tr><td>https://example.com/blog?a=1&amp;b=2</td><td>1</td><td>3</td></tr>"));
+    assert!(html_page.contains("<tr><td>https://example.com/docs</td><td>1</td><td>1</td></tr>"));
+}
+
+#[actix_rt::test]
+async fn tracked_links_cannot_redirect_anywhere_else() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft_with_links(&app).await;
+    let (_, html_body) = publish_and_deliver(&app, draft_id, &["track_clicks"]).await;
+    let tracked = &hrefs(&html_body, &app.address)[0];
+    let (prefix, _) = tracked.rsplit_once('-').unwrap();
+    let test_cases = vec![
+        format!("{}-99", prefix),
+        "/t/c/not-a-token".to_string(),
+        "/t/c/https%3A%2F%2Fevil.example".to_string(),
+    ];
+
+    for path in test_cases {
+        // Act
+        let response = app
+            .api_client
+            .get(&format!("{}{}", app.address, path))
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(404, response.status().as_u16(), "{} did not 404.", path);
+    }
+}
+
+#[actix_rt::test]
+async fn links_are_left_alone_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft_with_links(&app).await;
+
+    // Act
+    let (issue_id, html_body) = publish_and_deliver(&app, draft_id, &[]).await;
+
+    // Assert
+    assert!(!html_body.contains("/t/c/"));
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+    assert!(html_page.contains("<p>Clicks are not tracked for this issue.</p>"));
+}
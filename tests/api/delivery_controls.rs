@@ -0,0 +1,216 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_sample_draft, insert_confirmed_subscriber, publish_and_deliver,
+    spawn_app, AcceptEveryEmail, TestApp,
+};
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+/// Publish the sample draft without delivering it and return the id of the
+/// issue.
+async fn publish_sample_issue(app: &TestApp) -> Uuid {
+    let draft_id = create_sample_draft(app).await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "draft_id": draft_id,
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!(
+        "SELECT published_issue_id FROM newsletter_drafts WHERE draft_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .published_issue_id
+    .unwrap()
+}
+
+async fn get_delivery_statuses(app: &TestApp, issue_id: Uuid) -> Vec<String> {
+    sqlx::query!(
+        r#"
+        SELECT status
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        ORDER BY subscriber_email
+        "#,
+        issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.status)
+    .collect()
+}
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_pause_a_delivery() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_delivery_action(Uuid::new_v4(), "pause").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_rt::test]
+async fn paused_issues_are_not_delivered_until_they_are_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "alice@example.com", "reader").await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_sample_issue(&app).await;
+
+    // Act - Part 1 - Pause
+    let response = app.post_delivery_action(issue_id, "pause").await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .all(|r| r.url.path() != "/email/batch"));
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+    assert!(
+        html_page.contains("<p><i>The delivery of the newsletter issue has been paused.</i></p>")
+    );
+    assert!(html_page.contains("<p>Delivery is paused.</p>"));
+
+    // Act - Part 2 - Resume
+    let response = app.post_delivery_action(issue_id, "resume").await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    assert_eq!(get_delivery_statuses(&app, issue_id).await, vec!["sent"]);
+    // Mock verifies on Drop that the issue has been delivered once
+}
+
+#[actix_rt::test]
+async fn cancelling_an_issue_leaves_its_remaining_recipients_unsent() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "alice@example.com", "reader").await;
+    insert_confirmed_subscriber(&app, "bob@example.com", "reader").await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_sample_issue(&app).await;
+
+    // Act
+    let response = app.post_delivery_action(issue_id, "cancel").await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(
+        get_delivery_statuses(&app, issue_id).await,
+        vec!["cancelled", "cancelled"]
+    );
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been cancelled. \
+        2 queued deliveries will not be sent.</i></p>"
+    ));
+    assert!(html_page.contains("<p>This issue has been cancelled.</p>"));
+    assert!(html_page.contains(&format!(
+        "<tr><td>cancelled</td><td>{}</td>",
+        app.test_user.username
+    )));
+}
+
+#[actix_rt::test]
+async fn cancelling_does_not_wait_for_deliveries_a_worker_is_busy_with() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "alice@example.com", "reader").await;
+    insert_confirmed_subscriber(&app, "bob@example.com", "reader").await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_sample_issue(&app).await;
+    // A worker is recording the outcome of one of the deliveries.
+    let mut worker_transaction = app.db_pool.begin().await.unwrap();
+    sqlx::query!(
+        r#"
+        SELECT subscriber_email
+        FROM issue_delivery_queue
+        WHERE subscriber_email = 'alice@example.com'
+        FOR UPDATE
+        "#
+    )
+    .fetch_one(&mut worker_transaction)
+    .await
+    .unwrap();
+
+    // Act
+    let response = tokio::time::timeout(
+        Duration::from_secs(5),
+        app.post_delivery_action(issue_id, "cancel"),
+    )
+    .await
+    .expect("Cancelling waited for the worker.");
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    worker_transaction.rollback().await.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(
+        get_delivery_statuses(&app, issue_id).await,
+        vec!["cancelled", "cancelled"]
+    );
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been cancelled. \
+        1 queued deliveries will not be sent.</i></p>"
+    ));
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.is_empty());
+}
+
+#[actix_rt::test]
+async fn issues_that_have_been_delivered_cannot_be_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "alice@example.com", "reader").await;
+    app.test_user.login(&app).await;
+    let draft_id = create_sample_draft(&app).await;
+    let (issue_id, _) = publish_and_deliver(&app, draft_id, &[]).await;
+
+    // Act
+    let response = app.post_delivery_action(issue_id, "cancel").await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+
+    // Assert
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+    assert!(html_page.contains("<p><i>The newsletter issue is not being delivered.</i></p>"));
+    assert!(!html_page.contains("<h2>History</h2>"));
+    assert_eq!(get_delivery_statuses(&app, issue_id).await, vec!["sent"]);
+}
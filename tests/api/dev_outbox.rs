@@ -0,0 +1,126 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::{EmailBackend, Environment, OutboxSettings};
+
+async fn spawn_app_with_outbox() -> TestApp {
+    spawn_app_with(|c| {
+        c.email_client.backend = EmailBackend::Outbox;
+        c.email_client.outbox = Some(OutboxSettings {
+            directory: std::env::temp_dir()
+                .join(uuid::Uuid::new_v4().to_string())
+                .to_str()
+                .unwrap()
+                .into(),
+        });
+    })
+    .await
+}
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_browse_the_outbox() {
+    // Arrange
+    let app = spawn_app_with_outbox().await;
+
+    // Act
+    let response = app.get_dev_outbox().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_rt::test]
+async fn emails_are_written_to_the_outbox_instead_of_being_sent() {
+    // Arrange
+    let app = spawn_app_with_outbox().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Assert
+    let html_page = app.get_dev_outbox_html().await;
+    assert!(html_page.contains("<td>ursula_le_guin@gmail.com</td>"));
+    let message_path = html_page
+        .split(r#"<a href=""#)
+        .find_map(|s| s.strip_prefix("/admin/dev/outbox/"))
+        .and_then(|s| s.split('"').next())
+        .map(|id| format!("/admin/dev/outbox/{}", id))
+        .expect("The message is not listed.");
+    let response = app
+        .api_client
+        .get(&format!("{}{}", app.address, message_path))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Welcome!</h1>"));
+    assert!(html_page.contains("/subscriptions/confirm?subscription_token="));
+
+    let response = app
+        .api_client
+        .get(&format!("{}{}/raw", app.address, message_path))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let eml = response.text().await.unwrap();
+    assert!(eml.contains("To: ursula_le_guin@gmail.com"));
+    assert!(eml.contains("Subject: Welcome!"));
+}
+
+#[actix_rt::test]
+async fn unknown_outbox_messages_return_404() {
+    // Arrange
+    let app = spawn_app_with_outbox().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(&format!(
+            "{}/admin/dev/outbox/20220101000000000000-abc",
+            app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_rt::test]
+async fn the_outbox_page_says_when_emails_are_actually_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app.get_dev_outbox_html().await;
+
+    // Assert
+    assert!(html_page.contains("Emails are not written to an outbox"));
+}
+
+#[actix_rt::test]
+async fn the_outbox_is_not_available_outside_of_the_local_environment() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.environment = Environment::Production).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_dev_outbox().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,197 @@
+use crate::helpers::{create_confirmed_subscriber, publish_newsletter, spawn_app, TestApp};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+fn bounce(id: i64, bounce_type: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": id,
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Email": "ursula_le_guin@gmail.com",
+        "Description": "The server was unable to deliver your message.",
+        "BouncedAt": "2022-04-30T10:00:00Z",
+    })
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+#[actix_rt::test]
+async fn hard_bounces_take_the_subscriber_off_the_list() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app.post_email_event(&bounce(42, "HardBounce")).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[actix_rt::test]
+async fn queued_deliveries_to_addresses_that_bounced_are_skipped() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let response = app.post_email_event(&bounce(42, "HardBounce")).await;
+    assert_eq!(response.status().as_u16(), 200);
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!("SELECT status FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "skipped");
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[actix_rt::test]
+async fn spam_complaints_take_the_subscriber_off_the_list() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_email_event(&serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "ID": 43,
+            "Type": "SpamComplaint",
+            "TypeCode": 512,
+            "Email": "ursula_le_guin@gmail.com",
+            "BouncedAt": "2022-04-30T10:00:00Z",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "complained");
+}
+
+#[actix_rt::test]
+async fn soft_bounces_are_recorded_but_keep_the_subscriber_on_the_list() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app.post_email_event(&bounce(44, "SoftBounce")).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+    let event = sqlx::query!("SELECT record_type, event_type, subscriber_email FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.record_type, "Bounce");
+    assert_eq!(event.event_type, "SoftBounce");
+    assert_eq!(event.subscriber_email, "ursula_le_guin@gmail.com");
+}
+
+#[actix_rt::test]
+async fn redelivered_events_are_only_acted_upon_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_email_event(&bounce(45, "HardBounce")).await;
+    // The subscriber comes back after fixing their mailbox.
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - The provider retries the same webhook
+    let response = app.post_email_event(&bounce(45, "HardBounce")).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+    let n_events = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM email_events"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_events, 1);
+}
+
+#[actix_rt::test]
+async fn other_record_types_are_ignored() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_email_event(&serde_json::json!({
+            "RecordType": "Delivery",
+            "Recipient": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_rt::test]
+async fn requests_without_valid_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let test_cases = vec![
+        (None, "no credentials"),
+        (Some("wrong-password"), "a wrong password"),
+    ];
+
+    for (password, description) in test_cases {
+        // Act
+        let mut request = app
+            .api_client
+            .post(&format!("{}/webhooks/email-events", &app.address))
+            .json(&bounce(46, "HardBounce"));
+        if let Some(password) = password {
+            request = request.basic_auth(&app.email_events_webhook.username, Some(password));
+        }
+        let response = request.send().await.expect("Failed to execute request.");
+
+        // Assert
+        assert_eq!(
+            401,
+            response.status().as_u16(),
+            "The webhook did not reject a request with {}.",
+            description
+        );
+        assert_eq!(
+            r#"Basic realm="email-events""#,
+            response.headers()["WWW-Authenticate"]
+        );
+    }
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
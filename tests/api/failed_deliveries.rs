@@ -0,0 +1,552 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, insert_confirmed_subscriber,
+    publish_newsletter, spawn_app, AcceptEveryEmail, TestApp,
+};
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::email_client::{EmailHeader, EmailTransport, SendError};
+use zero2prod::issue_delivery_worker::{try_execute_task, MAX_DELIVERY_ATTEMPTS};
+
+async fn make_queued_tasks_due(app: &TestApp) {
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_see_failed_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_failed_deliveries().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_rt::test]
+async fn transient_failures_are_retried_later() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - First attempt fails
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - The task has been rescheduled, not dropped
+    let task = sqlx::query!("SELECT n_retries, execute_after FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The failed task is no longer in the queue.");
+    assert_eq!(task.n_retries, 1);
+    assert!(task.execute_after > chrono::Utc::now());
+
+    // Act - Second attempt succeeds
+    make_queued_tasks_due(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let queued = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.is_empty());
+    let failed = sqlx::query!("SELECT subscriber_email FROM failed_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(failed.is_empty());
+}
+
+#[actix_rt::test]
+async fn permanent_failures_are_moved_to_failed_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let queued = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.is_empty());
+    let failed = sqlx::query!("SELECT subscriber_email, n_attempts FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The failed delivery was not recorded.");
+    assert_eq!(failed.subscriber_email, "ursula_le_guin@gmail.com");
+    assert_eq!(failed.n_attempts, 1);
+
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+}
+
+#[actix_rt::test]
+async fn issues_that_cannot_be_rendered_are_moved_to_failed_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    // Issues published before bodies were templated were never validated.
+    sqlx::query!("UPDATE newsletter_issues SET text_content = 'Hello {{ subscriber_name'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let failed = sqlx::query!("SELECT last_error FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The failed delivery was not recorded.");
+    assert!(failed
+        .last_error
+        .starts_with("The issue cannot be rendered."));
+    let delivery = sqlx::query!("SELECT status FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "failed");
+}
+
+#[actix_rt::test]
+async fn messages_rejected_from_a_batch_are_moved_to_failed_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    insert_confirmed_subscriber(&app, "inactive@example.com", "inactive").await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(RejectInactiveRecipients)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let failed = sqlx::query!("SELECT subscriber_email, last_error FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The rejected delivery was not recorded.");
+    assert_eq!(failed.subscriber_email, "inactive@example.com");
+    assert!(failed.last_error.contains("inactive recipient"));
+    let sent = sqlx::query!("SELECT subscriber_email FROM issue_deliveries WHERE status = 'sent'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(sent.subscriber_email, "ursula_le_guin@gmail.com");
+}
+
+/// Accepts every message of a batch but those sent to `inactive@example.com`.
+struct RejectInactiveRecipients;
+
+impl wiremock::Respond for RejectInactiveRecipients {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<_> = messages
+            .iter()
+            .map(|m| match m["To"].as_str() {
+                Some("inactive@example.com") => serde_json::json!({
+                    "ErrorCode": 406,
+                    "Message": "You tried to send to an inactive recipient.",
+                }),
+                _ => serde_json::json!({ "ErrorCode": 0, "Message": "OK" }),
+            })
+            .collect();
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+}
+
+#[actix_rt::test]
+async fn deliveries_are_moved_to_failed_deliveries_once_attempts_run_out() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET n_retries = $1",
+        MAX_DELIVERY_ATTEMPTS - 1
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let failed = sqlx::query!("SELECT n_attempts FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The failed delivery was not recorded.");
+    assert_eq!(failed.n_attempts, MAX_DELIVERY_ATTEMPTS);
+}
+
+#[actix_rt::test]
+async fn failed_deliveries_can_be_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+
+    let failure_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(400))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    drop(failure_guard);
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - Re-queue the delivery
+    let response = app
+        .post_failed_deliveries(&serde_json::json!({
+            "newsletter_issue_id": issue.newsletter_issue_id,
+            "subscriber_email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/failed_deliveries");
+
+    // Act - Follow the redirect
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page
+        .contains("<p><i>The delivery to ursula_le_guin@gmail.com has been re-queued.</i></p>"));
+    assert!(html_page.contains("There are no failed deliveries."));
+
+    // Act - Deliver the re-queued task
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that the newsletter has been delivered
+}
+
+#[actix_rt::test]
+async fn failed_deliveries_of_cancelled_issues_cannot_be_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(400))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE newsletter_issues SET status = 'cancelled'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_failed_deliveries(&serde_json::json!({
+            "newsletter_issue_id": issue.newsletter_issue_id,
+            "subscriber_email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/failed_deliveries");
+
+    // Assert
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains(
+        "<p><i>The delivery to ursula_le_guin@gmail.com cannot be re-queued: \
+        the issue has been cancelled.</i></p>"
+    ));
+    let queued = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.is_empty());
+    let failed = sqlx::query!("SELECT subscriber_email FROM failed_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failed.len(), 1);
+}
+
+#[actix_rt::test]
+async fn the_email_of_an_unknown_failed_delivery_is_escaped() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_failed_deliveries(&serde_json::json!({
+            "newsletter_issue_id": Uuid::new_v4(),
+            "subscriber_email": "<script>alert('hi')</script>",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/failed_deliveries");
+
+    // Assert
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("&lt;script&gt;alert(&#x27;hi&#x27;)&lt;/script&gt;"));
+    assert!(!html_page.contains("<script>"));
+}
+
+#[actix_rt::test]
+async fn deliveries_interrupted_while_sending_are_not_sent_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    // A worker died after handing the message over to the email provider,
+    // before it could record the outcome.
+    sqlx::query!("UPDATE issue_deliveries SET status = 'sending'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let queued = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.is_empty());
+    let delivery = sqlx::query!("SELECT status FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "failed");
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+    assert!(html_page.contains("The worker stopped while the email was being sent"));
+}
+
+/// Sends one message at a time and hangs on the second one, as a worker
+/// that dies while sending.
+#[derive(Default)]
+struct HangAfterFirstMessage(AtomicUsize);
+
+#[async_trait::async_trait]
+impl EmailTransport for HangAfterFirstMessage {
+    async fn send_email(
+        &self,
+        _recipient: &SubscriberEmail,
+        _subject: &str,
+        _html_content: &str,
+        _text_content: &str,
+        _headers: &[EmailHeader],
+    ) -> Result<(), SendError> {
+        if self.0.fetch_add(1, Ordering::SeqCst) > 0 {
+            std::future::pending::<()>().await;
+        }
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn deliveries_a_dead_worker_did_not_get_to_are_sent_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    insert_confirmed_subscriber(&app, "octavia_butler@gmail.com", "butler").await;
+    insert_confirmed_subscriber(&app, "nk_jemisin@gmail.com", "jemisin").await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+
+    // Act - The worker dies while sending the second message
+    let transport = HangAfterFirstMessage::default();
+    let outcome = tokio::time::timeout(
+        Duration::from_secs(1),
+        try_execute_task(&app.db_pool, &transport, &app.address, &app.hmac_secret),
+    )
+    .await;
+    assert!(outcome.is_err(), "The worker was not stuck.");
+
+    // Act - Another worker picks the batch up once the claim runs out
+    make_queued_tasks_due(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let mut statuses: Vec<String> = sqlx::query!("SELECT status FROM issue_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.status)
+        .collect();
+    statuses.sort();
+    assert_eq!(statuses, vec!["failed", "sent", "sent"]);
+    let failed = sqlx::query!("SELECT last_error FROM failed_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failed.len(), 1);
+    assert!(failed[0]
+        .last_error
+        .starts_with("The worker stopped while the email was being sent"));
+    let batch_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|r| r.url.path() == "/email/batch")
+        .unwrap();
+    let batch: Vec<serde_json::Value> = serde_json::from_slice(&batch_request.body).unwrap();
+    assert_eq!(batch.len(), 1);
+}
+
+/// A worker whose claim runs out while it sends the first message: another
+/// worker claims the batch and dies while sending it.
+struct TakeOverAfterFirstMessage<'a> {
+    pool: &'a PgPool,
+    address: &'a str,
+    hmac_secret: &'a Secret<String>,
+    n_messages: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for TakeOverAfterFirstMessage<'_> {
+    async fn send_email(
+        &self,
+        _recipient: &SubscriberEmail,
+        _subject: &str,
+        _html_content: &str,
+        _text_content: &str,
+        _headers: &[EmailHeader],
+    ) -> Result<(), SendError> {
+        if self.n_messages.fetch_add(1, Ordering::SeqCst) == 0 {
+            sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+                .execute(self.pool)
+                .await
+                .unwrap();
+            let transport = HangAfterFirstMessage::default();
+            let outcome = tokio::time::timeout(
+                Duration::from_secs(1),
+                try_execute_task(self.pool, &transport, self.address, self.hmac_secret),
+            )
+            .await;
+            assert!(outcome.is_err(), "The other worker was not stuck.");
+        }
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn workers_do_not_send_deliveries_another_worker_claimed_after_their_claim_ran_out() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    insert_confirmed_subscriber(&app, "octavia_butler@gmail.com", "butler").await;
+    insert_confirmed_subscriber(&app, "nk_jemisin@gmail.com", "jemisin").await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let transport = TakeOverAfterFirstMessage {
+        pool: &app.db_pool,
+        address: &app.address,
+        hmac_secret: &app.hmac_secret,
+        n_messages: AtomicUsize::new(0),
+    };
+
+    // Act
+    try_execute_task(&app.db_pool, &transport, &app.address, &app.hmac_secret)
+        .await
+        .unwrap();
+
+    // Assert - The other worker sent the second message and is still
+    // sending the third one: neither goes out again.
+    assert_eq!(transport.n_messages.load(Ordering::SeqCst), 1);
+    let n_sending = sqlx::query!(
+        r#"SELECT COUNT(*) as "count!" FROM issue_deliveries WHERE status = 'sending'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_sending, 1);
+}
@@ -0,0 +1,132 @@
+use crate::helpers::{assert_is_redirect_to, publish_newsletter, spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn get_issue_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn get_conditionally(
+    app: &TestApp,
+    path: &str,
+    header: &str,
+    value: &str,
+) -> reqwest::Response {
+    app.api_client
+        .get(&format!("{}{}", &app.address, path))
+        .header(header, value)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[actix_rt::test]
+async fn published_issues_are_in_the_feeds() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    app.post_logout().await;
+
+    for (path, content_type) in [
+        ("/feed.rss", "application/rss+xml; charset=utf-8"),
+        ("/feed.atom", "application/atom+xml; charset=utf-8"),
+    ] {
+        // Act
+        let response = app.get_feed(path).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200, "Feed: {}", path);
+        assert_eq!(response.headers()["Content-Type"], content_type);
+        assert!(response.headers().contains_key("ETag"));
+        assert!(response.headers().contains_key("Last-Modified"));
+        let feed = response.text().await.unwrap();
+        assert!(
+            feed.contains("<title>Newsletter title</title>"),
+            "Feed: {}",
+            path
+        );
+        assert!(feed.contains("/archive/newsletter-title"), "Feed: {}", path);
+        assert!(
+            feed.contains("&lt;p&gt;Newsletter body as HTML&lt;/p&gt;"),
+            "Feed: {}",
+            path
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn hidden_issues_are_not_in_the_feeds() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let issue_id = get_issue_id(&app).await;
+    let response = app.get_feed("/feed.atom").await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+
+    // Act
+    let response = app
+        .post_archive_visibility(
+            issue_id,
+            &serde_json::json!({ "hidden_from_archive": true }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+
+    // Assert
+    let response = get_conditionally(&app, "/feed.atom", "If-None-Match", &etag).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(response.headers()["ETag"].to_str().unwrap(), etag);
+    let feed = response.text().await.unwrap();
+    assert!(!feed.contains("Newsletter title"));
+}
+
+#[actix_rt::test]
+async fn unchanged_feeds_are_not_sent_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+
+    for path in ["/feed.rss", "/feed.atom"] {
+        let response = app.get_feed(path).await;
+        let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+        let last_modified = response.headers()["Last-Modified"]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        // Act
+        let by_etag = get_conditionally(&app, path, "If-None-Match", &etag).await;
+        let by_date = get_conditionally(&app, path, "If-Modified-Since", &last_modified).await;
+
+        // Assert
+        for response in [by_etag, by_date] {
+            assert_eq!(response.status().as_u16(), 304, "Feed: {}", path);
+            assert!(response.text().await.unwrap().is_empty());
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn feeds_change_when_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let response = app.get_feed("/feed.rss").await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_string();
+
+    // Act
+    publish_newsletter(&app).await;
+
+    // Assert
+    let response = get_conditionally(&app, "/feed.rss", "If-None-Match", &etag).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("/archive/newsletter-title-2"));
+}
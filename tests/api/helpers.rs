@@ -2,10 +2,18 @@ use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use once_cell::sync::Lazy;
 use reqwest::{Response, Url};
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{
+    get_configuration, DatabaseSettings, EmailBackend, Settings, WebhookSettings,
+};
+use zero2prod::email_client::EmailTransport;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::issue_scheduler::{try_complete_subject_test, try_release_issue, ReleaseOutcome};
 use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -26,6 +34,9 @@ pub struct TestApp {
     pub port: u16,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub email_client: Arc<dyn EmailTransport>,
+    pub email_events_webhook: WebhookSettings,
+    pub hmac_secret: Secret<String>,
 }
 
 pub struct ConfirmationLinks {
@@ -78,6 +89,288 @@ impl TestApp {
         get_html(self.get_send_newsletter().await).await
     }
 
+    pub async fn get_failed_deliveries(&self) -> reqwest::Response {
+        self.get_route(String::from("/admin/failed_deliveries")).await
+    }
+
+    pub async fn get_failed_deliveries_html(&self) -> String {
+        get_html(self.get_failed_deliveries().await).await
+    }
+
+    /// Report an event the way the email provider does.
+    pub async fn post_email_event(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/webhooks/email-events", &self.address))
+            .basic_auth(
+                &self.email_events_webhook.username,
+                Some(self.email_events_webhook.password.expose_secret()),
+            )
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_dev_outbox(&self) -> reqwest::Response {
+        self.get_route(String::from("/admin/dev/outbox")).await
+    }
+
+    pub async fn get_dev_outbox_html(&self) -> String {
+        get_html(self.get_dev_outbox().await).await
+    }
+
+    pub async fn post_failed_deliveries<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/failed_deliveries", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_lists(&self) -> reqwest::Response {
+        self.get_route(String::from("/admin/lists")).await
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        get_html(self.get_lists().await).await
+    }
+
+    pub async fn post_lists<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/lists", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Create a mailing list and return its id.
+    pub async fn create_list(&self, name: &str) -> Uuid {
+        let response = self.post_lists(&serde_json::json!({ "name": name })).await;
+        assert_is_redirect_to(&response, "/admin/lists");
+        sqlx::query!("SELECT list_id FROM lists WHERE name = $1", name)
+            .fetch_one(&self.db_pool)
+            .await
+            .unwrap()
+            .list_id
+    }
+
+    pub async fn get_subscribers_html(&self, query: &str) -> String {
+        get_html(self.get_route(format!("/admin/subscribers?{}", query)).await).await
+    }
+
+    pub async fn post_subscriber_tags<Body>(&self, subscriber_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/subscribers/{}/tags", &self.address, subscriber_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_recipient_count(&self, query: &str) -> reqwest::Response {
+        self.get_route(format!("/admin/newsletters/recipients?{}", query))
+            .await
+    }
+
+    pub async fn get_newsletter_issue_report(&self, issue_id: Uuid, query: &str) -> reqwest::Response {
+        self.get_route(format!("/admin/newsletters/{}?{}", issue_id, query))
+            .await
+    }
+
+    pub async fn get_newsletter_issue_report_html(&self, issue_id: Uuid, query: &str) -> String {
+        get_html(self.get_newsletter_issue_report(issue_id, query).await).await
+    }
+
+    pub async fn get_issue_stats(&self, issue_id: Uuid) -> reqwest::Response {
+        self.get_route(format!("/admin/newsletters/{}/stats", issue_id))
+            .await
+    }
+
+    pub async fn get_issue_stats_html(&self, issue_id: Uuid) -> String {
+        get_html(self.get_issue_stats(issue_id).await).await
+    }
+
+    pub async fn get_issue_stats_csv(&self, issue_id: Uuid) -> reqwest::Response {
+        self.get_route(format!("/admin/newsletters/{}/stats.csv", issue_id))
+            .await
+    }
+
+    pub async fn post_archive_visibility<Body>(&self, issue_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!(
+                "{}/admin/newsletters/{}/archive",
+                &self.address, issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_archive_html(&self) -> String {
+        get_html(self.get_route(String::from("/archive")).await).await
+    }
+
+    pub async fn get_archived_issue(&self, slug: &str) -> reqwest::Response {
+        self.get_route(format!("/archive/{}", slug)).await
+    }
+
+    /// `path` is `/feed.rss` or `/feed.atom`.
+    pub async fn get_feed(&self, path: &str) -> reqwest::Response {
+        self.get_route(String::from(path)).await
+    }
+
+    pub async fn get_scheduled_newsletters(&self) -> reqwest::Response {
+        self.get_route(String::from("/admin/newsletters/scheduled")).await
+    }
+
+    pub async fn get_scheduled_newsletters_html(&self) -> String {
+        get_html(self.get_scheduled_newsletters().await).await
+    }
+
+    pub async fn post_reschedule_newsletter<Body>(&self, issue_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/newsletters/{}/reschedule", &self.address, issue_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_cancel_newsletter(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/newsletters/{}/cancel", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// `action` is one of `pause`, `resume` or `cancel`.
+    pub async fn post_delivery_action(&self, issue_id: Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!(
+                "{}/admin/newsletters/{}/delivery/{}",
+                &self.address, issue_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_drafts<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/newsletters/drafts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Create a draft and return its id, extracted from the redirect to its editor.
+    pub async fn create_draft<Body>(&self, body: &Body) -> Uuid
+    where
+        Body: serde::Serialize,
+    {
+        let response = self.post_drafts(body).await;
+        assert_eq!(response.status().as_u16(), 303);
+        response
+            .headers()
+            .get("Location")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .trim_start_matches("/admin/newsletters/drafts/")
+            .parse()
+            .unwrap()
+    }
+
+    pub async fn get_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        self.get_route(format!("/admin/newsletters/drafts/{}", draft_id))
+            .await
+    }
+
+    pub async fn get_draft_html(&self, draft_id: Uuid) -> String {
+        get_html(self.get_draft(draft_id).await).await
+    }
+
+    pub async fn get_draft_preview(&self, draft_id: Uuid) -> reqwest::Response {
+        self.get_route(format!("/admin/newsletters/drafts/{}/preview", draft_id))
+            .await
+    }
+
+    pub async fn post_draft<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/newsletters/drafts/{}", &self.address, draft_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_autosave_draft<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!(
+                "{}/admin/newsletters/drafts/{}/autosave",
+                &self.address, draft_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_test_email<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!(
+                "{}/admin/newsletters/drafts/{}/test",
+                &self.address, draft_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_delete_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(&format!(
+                "{}/admin/newsletters/drafts/{}/delete",
+                &self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -108,6 +401,23 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_unsubscribe(&self, subscription_token: &str) -> reqwest::Response {
+        self.get_route(format!(
+            "/subscriptions/unsubscribe?subscription_token={}",
+            subscription_token
+        ))
+        .await
+    }
+
+    pub async fn post_unsubscribe(&self, subscription_token: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(&serde_json::json!({ "subscription_token": subscription_token }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_newsletters<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -120,6 +430,40 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.address,
+                &self.hmac_secret,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn release_due_issues(&self) {
+        loop {
+            if let ReleaseOutcome::NothingDue = try_release_issue(&self.db_pool).await.unwrap() {
+                break;
+            }
+        }
+    }
+
+    pub async fn complete_due_subject_tests(&self) {
+        loop {
+            if let ReleaseOutcome::NothingDue =
+                try_complete_subject_test(&self.db_pool).await.unwrap()
+            {
+                break;
+            }
+        }
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -197,6 +541,14 @@ impl TestUser {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like `spawn_app`, with a chance to tweak the configuration first.
+pub async fn spawn_app_with<F>(configure: F) -> TestApp
+where
+    F: FnOnce(&mut Settings),
+{
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -206,6 +558,9 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        // Whatever the local configuration says, emails go to the mock server.
+        c.email_client.backend = EmailBackend::Postmark;
+        configure(&mut c);
         c
     };
 
@@ -232,17 +587,157 @@ pub async fn spawn_app() -> TestApp {
         port: application_port,
         test_user: TestUser::generate(),
         api_client: client,
+        email_client: configuration.email_client.client(),
+        email_events_webhook: configuration.email_events_webhook,
+        hmac_secret: configuration.application.hmac_secret,
     };
     test_app.test_user.store(&test_app.db_pool).await;
 
     test_app
 }
 
+/// Replies to `/email/batch` the way Postmark does when it accepts every
+/// message of the batch.
+pub struct AcceptEveryEmail;
+
+impl wiremock::Respond for AcceptEveryEmail {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<_> = messages
+            .iter()
+            .map(|m| serde_json::json!({ "ErrorCode": 0, "Message": "OK", "To": m["To"] }))
+            .collect();
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+}
+
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
 }
 
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// A confirmed member of the default list, without going through the
+/// subscription flow: `email` does not have to be valid.
+pub async fn insert_confirmed_subscriber(app: &TestApp, email: &str, name: &str) {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, now(), 'confirmed')"#,
+        subscriber_id,
+        email,
+        name
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+        SELECT list_id, $1, 'confirmed', now() FROM lists WHERE is_default"#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+pub async fn create_sample_draft(app: &TestApp) -> Uuid {
+    app.create_draft(&serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text",
+    }))
+    .await
+}
+
+pub async fn publish_newsletter(app: &TestApp) {
+    let draft_id = create_sample_draft(app).await;
+    let newsletter_request_body = serde_json::json!({
+        "draft_id": draft_id,
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+/// Publish a draft to every confirmed subscriber, ticking the given
+/// tracking checkboxes, deliver it and return the id of the issue and the
+/// HTML body that was sent.
+pub async fn publish_and_deliver(
+    app: &TestApp,
+    draft_id: Uuid,
+    tracking: &[&str],
+) -> (Uuid, String) {
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let mut body = serde_json::json!({
+        "draft_id": draft_id,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    for checkbox in tracking {
+        body[*checkbox] = "on".into();
+    }
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let issue_id = sqlx::query!(
+        "SELECT published_issue_id FROM newsletter_drafts WHERE draft_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .published_issue_id
+    .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let batch: Vec<serde_json::Value> = serde_json::from_slice(&email_request.body).unwrap();
+    (issue_id, batch[0]["HtmlBody"].as_str().unwrap().to_string())
+}
+
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
     let mut connection = PgConnection::connect_with(&config.without_db())
         .await
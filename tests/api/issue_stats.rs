@@ -0,0 +1,196 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_sample_draft, publish_and_deliver,
+    spawn_app, TestApp,
+};
+use uuid::Uuid;
+
+async fn get_subscription_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token
+}
+
+/// Follow the unsubscribe link of an issue and confirm.
+async fn unsubscribe_from_issue(app: &TestApp, issue_id: Uuid) {
+    let response = app
+        .api_client
+        .post(&format!("{}/subscriptions/unsubscribe", &app.address))
+        .form(&serde_json::json!({
+            "subscription_token": get_subscription_token(app).await,
+            "issue_id": issue_id,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_see_the_statistics_of_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_issue_stats(Uuid::new_v4()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_rt::test]
+async fn the_statistics_of_an_unknown_issue_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_response = app.get_issue_stats(Uuid::new_v4()).await;
+    let csv_response = app.get_issue_stats_csv(Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(html_response.status().as_u16(), 404);
+    assert_eq!(csv_response.status().as_u16(), 404);
+}
+
+#[actix_rt::test]
+async fn the_statistics_count_deliveries_opens_and_bounces() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let (issue_id, html_body) =
+        publish_and_deliver(&app, create_sample_draft(&app).await, &["track_opens"]).await;
+    let start = html_body.find("/t/o/").unwrap();
+    let end = start + html_body[start..].find('"').unwrap();
+    reqwest::get(&format!("{}{}", app.address, &html_body[start..end]))
+        .await
+        .unwrap();
+    let response = app
+        .post_email_event(&serde_json::json!({
+            "RecordType": "Bounce",
+            "ID": 42,
+            "Type": "SoftBounce",
+            "TypeCode": 4096,
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Email": "ursula_le_guin@gmail.com",
+            "Description": "The server was unable to deliver your message.",
+            "BouncedAt": "2022-04-30T10:00:00Z",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    let html_page = app.get_issue_stats_html(issue_id).await;
+
+    // Assert
+    assert!(html_page.contains("<tr><td>Delivered</td><td>1</td><td>100.0%</td></tr>"));
+    assert!(html_page.contains("<tr><td>Bounced</td><td>1</td><td>100.0%</td></tr>"));
+    assert!(html_page.contains("<tr><td>Opened</td><td>1</td><td>100.0%</td></tr>"));
+    assert!(html_page.contains("<tr><td>Clicked</td><td>not tracked</td><td></td></tr>"));
+    assert!(html_page.contains("<tr><td>Unsubscribed</td><td>0</td><td>0.0%</td></tr>"));
+    assert!(html_page.contains("<tr><td>0-1</td><td>1</td><td>1</td></tr>"));
+    assert!(html_page.contains("<tr><td>71-72</td><td>0</td><td>1</td></tr>"));
+}
+
+#[actix_rt::test]
+async fn unsubscribing_through_an_issue_is_counted_against_it() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = app
+        .create_draft(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": r#"<a href="{{ unsubscribe_url }}">Unsubscribe</a>"#,
+            "text_content": "Unsubscribe: {{ unsubscribe_url }}",
+        }))
+        .await;
+    let (issue_id, html_body) = publish_and_deliver(&app, draft_id, &[]).await;
+    assert!(html_body.contains(&format!("&issue_id={}", issue_id)));
+
+    // Act
+    unsubscribe_from_issue(&app, issue_id).await;
+    unsubscribe_from_issue(&app, issue_id).await;
+
+    // Assert
+    let html_page = app.get_issue_stats_html(issue_id).await;
+    assert!(html_page.contains("<tr><td>Unsubscribed</td><td>1</td><td>100.0%</td></tr>"));
+}
+
+#[actix_rt::test]
+async fn events_of_addresses_the_issue_was_not_delivered_to_are_not_counted() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let (issue_id, _) = publish_and_deliver(&app, create_sample_draft(&app).await, &[]).await;
+    // E.g. a forwarded copy of the issue.
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            provider_event_id, record_type, event_type, subscriber_email,
+            received_at, newsletter_issue_id
+        )
+        VALUES (42, 'Bounce', 'HardBounce', 'someone_else@example.com', now(), $1)
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_unsubscribes (newsletter_issue_id, subscriber_email, unsubscribed_at)
+        VALUES ($1, 'someone_else@example.com', now())
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let html_page = app.get_issue_stats_html(issue_id).await;
+
+    // Assert
+    assert!(html_page.contains("<tr><td>Delivered</td><td>1</td><td>100.0%</td></tr>"));
+    assert!(html_page.contains("<tr><td>Bounced</td><td>0</td><td>0.0%</td></tr>"));
+    assert!(html_page.contains("<tr><td>Unsubscribed</td><td>0</td><td>0.0%</td></tr>"));
+}
+
+#[actix_rt::test]
+async fn the_statistics_of_every_recipient_can_be_exported_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let (issue_id, _) = publish_and_deliver(&app, create_sample_draft(&app).await, &[]).await;
+    unsubscribe_from_issue(&app, issue_id).await;
+
+    // Act
+    let response = app.get_issue_stats_csv(issue_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    assert!(response.headers()["Content-Disposition"]
+        .to_str()
+        .unwrap()
+        .starts_with("attachment"));
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.split_terminator("\r\n").collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(
+        lines[0],
+        "email,status,updated_at,first_opened_at,opens,clicks,bounced,complained,unsubscribed_at"
+    );
+    let fields: Vec<&str> = lines[1].split(',').collect();
+    assert_eq!(fields[..2], ["ursula_le_guin@gmail.com", "sent"]);
+    assert_eq!(fields[3..8], ["", "0", "0", "false", "false"]);
+    assert!(!fields[8].is_empty());
+}
@@ -0,0 +1,204 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_sample_draft, spawn_app,
+    AcceptEveryEmail, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Subscribe `email` to `list_id` and follow the confirmation link.
+async fn subscribe_to_list(app: &TestApp, email: &str, list_id: Uuid) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!(
+        "name=reader&email={}&list_id={}",
+        email.replace('@', "%40"),
+        list_id
+    );
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn get_membership_statuses(app: &TestApp, email: &str) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT l.name, m.status
+        FROM list_memberships m
+        JOIN lists l USING (list_id)
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE s.email = $1
+        ORDER BY l.name
+        "#,
+        email
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.name, r.status))
+    .collect()
+}
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_lists().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_rt::test]
+async fn lists_can_be_created_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.create_list("Weekly digest").await;
+
+    // Act
+    let response = app
+        .post_lists(&serde_json::json!({ "name": "Weekly digest" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    // Assert
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("<p><i>There already is a list named \"Weekly digest\".</i></p>"));
+    assert!(html_page.contains("<td>Newsletter (default)</td>"));
+    assert!(html_page.contains("<td>Weekly digest</td>"));
+}
+
+#[actix_rt::test]
+async fn subscribers_join_the_default_list_unless_they_name_one() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    create_confirmed_subscriber(&app).await;
+
+    // Assert
+    assert_eq!(
+        get_membership_statuses(&app, "ursula_le_guin@gmail.com").await,
+        vec![("Newsletter".to_string(), "confirmed".to_string())]
+    );
+}
+
+#[actix_rt::test]
+async fn subscribing_to_an_unknown_list_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&list_id={}",
+        Uuid::new_v4()
+    );
+
+    // Act
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_rt::test]
+async fn issues_only_go_to_the_members_of_their_list() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let list_id = app.create_list("Weekly digest").await;
+    subscribe_to_list(&app, "digest-reader@example.com", list_id).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let draft_id = create_sample_draft(&app).await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "draft_id": draft_id,
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "list_id": list_id,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let batch: Vec<serde_json::Value> = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(batch.len(), 1);
+    assert_eq!(batch[0]["To"], "digest-reader@example.com");
+}
+
+#[actix_rt::test]
+async fn unsubscribing_from_a_list_keeps_the_other_memberships() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let list_id = app.create_list("Weekly digest").await;
+    subscribe_to_list(&app, "ursula_le_guin@gmail.com", list_id).await;
+    let subscription_token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token;
+
+    // Act
+    let response = app
+        .api_client
+        .post(&format!("{}/subscriptions/unsubscribe", &app.address))
+        .form(&serde_json::json!({
+            "subscription_token": subscription_token,
+            "list_id": list_id,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<p>You have been unsubscribed from Weekly digest.</p>"));
+    assert_eq!(
+        get_membership_statuses(&app, "ursula_le_guin@gmail.com").await,
+        vec![
+            ("Newsletter".to_string(), "confirmed".to_string()),
+            ("Weekly digest".to_string(), "unsubscribed".to_string()),
+        ]
+    );
+}
@@ -1,8 +1,24 @@
 mod admin_dashboard;
+mod archive;
 mod change_password;
+mod click_tracking;
+mod delivery_controls;
+mod dev_outbox;
+mod email_events;
+mod failed_deliveries;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issue_stats;
+mod lists;
 mod login;
+mod newsletter_drafts;
+mod newsletter_report;
 mod newsletters;
+mod open_tracking;
+mod scheduled_newsletters;
+mod subject_tests;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod tags;
@@ -0,0 +1,415 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_sample_draft, publish_newsletter,
+    spawn_app,
+};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_create_a_draft() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_drafts(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_rt::test]
+async fn drafts_can_be_edited_and_saved() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Create the draft
+    let draft_id = create_sample_draft(&app).await;
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>Your draft has been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="Newsletter title""#));
+
+    // Act - Save a new version
+    let response = app
+        .post_draft(
+            draft_id,
+            &serde_json::json!({
+                "title": "A better title",
+                "html_content": "<p>A better body</p>",
+                "text_content": "A better body",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/drafts/{}", draft_id));
+
+    // Assert
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains(r#"value="A better title""#));
+    assert!(html_page.contains("&lt;p&gt;A better body&lt;/p&gt;"));
+    let html_page = app.get_send_newsletter_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/newsletters/drafts/{}">A better title</a>"#,
+        draft_id
+    )));
+}
+
+#[actix_rt::test]
+async fn drafts_are_autosaved_in_the_background() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_sample_draft(&app).await;
+
+    // Act
+    let response = app
+        .post_autosave_draft(
+            draft_id,
+            &serde_json::json!({
+                "title": "Work in progress",
+                "html_content": "",
+                "text_content": "",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains(r#"value="Work in progress""#));
+}
+
+#[actix_rt::test]
+async fn drafts_can_be_previewed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_sample_draft(&app).await;
+
+    // Act
+    let response = app.get_draft_preview(draft_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"srcdoc="&lt;p&gt;Newsletter"#));
+    assert!(html_page.contains("<pre>Newsletter body as plain text</pre>"));
+}
+
+#[actix_rt::test]
+async fn drafts_are_previewed_with_their_template_variables_filled_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = app
+        .create_draft(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Hi {{ subscriber.name }}</p>",
+            "text_content": "Hi {{ subscriber.name }}! Unsubscribe: {{ unsubscribe_url }}",
+        }))
+        .await;
+
+    // Act
+    let response = app.get_draft_preview(draft_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        "<pre>Hi {}! Unsubscribe: http",
+        app.test_user.username
+    )));
+    assert!(html_page.contains("/subscriptions/unsubscribe</pre>"));
+    assert!(html_page.contains(&format!(
+        "<p>As {} &lt;subscriber@example.com&gt; would receive it.</p>",
+        app.test_user.username
+    )));
+    assert!(!html_page.contains("{{"));
+}
+
+#[actix_rt::test]
+async fn drafts_can_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_sample_draft(&app).await;
+
+    // Act
+    let response = app.post_delete_draft(draft_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let html_page = app.get_send_newsletter_html().await;
+    assert!(html_page.contains("<p><i>Your draft has been deleted.</i></p>"));
+    assert_eq!(app.get_draft(draft_id).await.status().as_u16(), 404);
+}
+
+#[actix_rt::test]
+async fn unknown_drafts_return_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_draft(Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_rt::test]
+async fn publishing_a_draft_takes_it_off_the_drafts_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_newsletter(&app).await;
+
+    // Assert
+    let draft = sqlx::query!("SELECT draft_id, published_issue_id FROM newsletter_drafts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_id = draft.published_issue_id.expect("The draft was not marked as published.");
+    let response = app.get_draft(draft.draft_id).await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+}
+
+#[actix_rt::test]
+async fn a_draft_cannot_be_published_twice() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_sample_draft(&app).await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "draft_id": draft_id,
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Publish again with a fresh idempotency key
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "draft_id": draft_id,
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let html_page = app.get_send_newsletter_html().await;
+    assert!(html_page.contains("<p><i>This draft has already been published.</i></p>"));
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+}
+
+#[actix_rt::test]
+async fn markdown_drafts_are_rendered_to_html_and_text() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let draft_id = app
+        .create_draft(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "# News\n\nRead [the blog](https://example.com/blog).\n\n<script>alert(1)</script>",
+            "html_content": "",
+            "text_content": "",
+        }))
+        .await;
+
+    // Assert
+    let draft = sqlx::query!(
+        "SELECT markdown_content, html_content, text_content FROM newsletter_drafts WHERE draft_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(draft.markdown_content.unwrap().starts_with("# News"));
+    assert!(draft.html_content.contains("<h1>News</h1>"));
+    assert!(draft.html_content.contains(r#"<a href="https://example.com/blog""#));
+    assert!(!draft.html_content.contains("<script>"));
+    assert_eq!(
+        draft.text_content,
+        "News\n====\n\nRead the blog [1].\n\n[1] https://example.com/blog"
+    );
+}
+
+#[actix_rt::test]
+async fn drafts_need_either_markdown_or_both_bodies() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        (serde_json::json!({ "title": "Newsletter title" }), "no content"),
+        (
+            serde_json::json!({
+                "title": "Newsletter title",
+                "markdown_content": " ",
+                "html_content": "<p>Newsletter body as HTML</p>",
+            }),
+            "missing text_content",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": "Newsletter body as plain text",
+            }),
+            "missing html_content",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        // Act
+        let response = app.post_drafts(&invalid_body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had {}.",
+            error_message
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_send_a_test_email() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_test_email(
+            Uuid::new_v4(),
+            &serde_json::json!({ "email": "editor@example.com" }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_rt::test]
+async fn test_emails_go_to_a_single_address_and_leave_no_trace() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = app
+        .create_draft(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Hi {{ subscriber.name }}</p>",
+            "text_content": "Hi {{ subscriber.name }}, read it online: {{ archive_url }}",
+        }))
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_test_email(draft_id, &serde_json::json!({ "email": "editor@example.com" }))
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/drafts/{}", draft_id));
+
+    // Assert
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>A test email has been sent to editor@example.com.</i></p>"));
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "editor@example.com");
+    assert_eq!(body["Subject"], "Newsletter title");
+    assert_eq!(
+        body["HtmlBody"].as_str().unwrap(),
+        format!("<p>Hi {}</p>", app.test_user.username)
+    );
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .ends_with("/archive/newsletter-title"));
+    assert_eq!(body["Headers"][0]["Name"], "List-Unsubscribe");
+    assert_eq!(body["Headers"][1]["Value"], "List-Unsubscribe=One-Click");
+    for table in ["newsletter_issues", "issue_delivery_queue", "issue_deliveries", "idempotency"] {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0, "A test send wrote to {}.", table);
+    }
+    let subscriber = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.status, "confirmed");
+}
+
+#[actix_rt::test]
+async fn test_emails_go_to_the_admin_address_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!(
+        "UPDATE users SET email = 'admin@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let draft_id = create_sample_draft(&app).await;
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains(r#"value="admin@example.com""#));
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_test_email(draft_id, &serde_json::json!({ "email": "" }))
+        .await;
+
+    // Assert
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>A test email has been sent to admin@example.com.</i></p>"));
+}
+
+#[actix_rt::test]
+async fn test_emails_need_an_address() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_sample_draft(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_test_email(draft_id, &serde_json::json!({ "email": "" }))
+        .await;
+
+    // Assert
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>Enter the address to send the test email to.</i></p>"));
+}
@@ -0,0 +1,188 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, insert_confirmed_subscriber,
+    publish_newsletter, spawn_app, AcceptEveryEmail, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+async fn create_confirmed_subscriber_with_invalid_email(app: &TestApp) {
+    insert_confirmed_subscriber(app, "not-an-email", "broken").await;
+}
+
+async fn get_issue_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_see_a_delivery_report() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_newsletter_issue_report(Uuid::new_v4(), "").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_rt::test]
+async fn unknown_issues_return_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_newsletter_issue_report(Uuid::new_v4(), "").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_rt::test]
+async fn the_delivery_report_tracks_the_status_of_each_recipient() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber_with_invalid_email(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let issue_id = get_issue_id(&app).await;
+
+    // Act - Before delivery
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+
+    // Assert
+    assert!(html_page.contains("<tr><td>queued</td><td>2</td></tr>"));
+
+    // Act - After delivery
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+
+    // Assert
+    assert!(html_page.contains("<tr><td>queued</td><td>0</td></tr>"));
+    assert!(html_page.contains("<tr><td>sent</td><td>1</td></tr>"));
+    assert!(html_page.contains("<tr><td>skipped</td><td>1</td></tr>"));
+    assert!(html_page.contains("<tr><td>total</td><td>2</td></tr>"));
+}
+
+#[actix_rt::test]
+async fn the_recipient_table_can_be_filtered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber_with_invalid_email(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let issue_id = get_issue_id(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act - Filter by status
+    let html_page = app
+        .get_newsletter_issue_report_html(issue_id, "status=skipped")
+        .await;
+
+    // Assert
+    assert!(html_page.contains("<td>not-an-email</td>"));
+    assert!(!html_page.contains("<td>ursula_le_guin@gmail.com</td>"));
+
+    // Act - Filter by email
+    let html_page = app
+        .get_newsletter_issue_report_html(issue_id, "email=le_guin")
+        .await;
+
+    // Assert
+    assert!(html_page.contains("<td>ursula_le_guin@gmail.com</td><td>sent</td>"));
+    assert!(!html_page.contains("<td>not-an-email</td>"));
+}
+
+#[actix_rt::test]
+async fn the_email_filter_matches_wildcard_characters_literally() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let issue_id = get_issue_id(&app).await;
+
+    // `%` and `_` would match any character in a `LIKE` pattern.
+    for filter in ["email=le%25guin", "email=gmail%5Fcom"] {
+        // Act
+        let html_page = app.get_newsletter_issue_report_html(issue_id, filter).await;
+
+        // Assert
+        assert!(
+            !html_page.contains("<td>ursula_le_guin@gmail.com</td>"),
+            "The filter `{}` matched.",
+            filter
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn the_recipient_table_says_when_it_is_truncated() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let issue_id = get_issue_id(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_email, status, updated_at)
+        SELECT $1, 'reader' || n || '@example.com', 'queued', now()
+        FROM generate_series(1, 1000) as n
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+
+    // Assert
+    assert!(html_page.contains("<p>Showing the first 1000 of 1001 matching recipients.</p>"));
+
+    // Act - Filter below the limit
+    let html_page = app
+        .get_newsletter_issue_report_html(issue_id, "email=reader1000@")
+        .await;
+
+    // Assert
+    assert!(!html_page.contains("Showing the first"));
+}
+
+#[actix_rt::test]
+async fn filtering_by_an_unknown_status_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let issue_id = get_issue_id(&app).await;
+
+    // Act
+    let response = app
+        .get_newsletter_issue_report(issue_id, "status=delivered")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
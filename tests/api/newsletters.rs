@@ -1,4 +1,8 @@
-use crate::helpers::{spawn_app, ConfirmationLinks, TestApp, assert_is_redirect_to};
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_sample_draft,
+    create_unconfirmed_subscriber, insert_confirmed_subscriber, publish_newsletter, spawn_app,
+    AcceptEveryEmail,
+};
 
 
 use wiremock::matchers::{any, method, path};
@@ -23,9 +27,7 @@ async fn you_must_be_logged_in_to_publish_a_newsletter() {
 
     // Act
     let newsletter_request_body = serde_json::json!({
-        "title": "Newsletter title",
-        "html_content": "<p>Newsletter body as HTML</p>",
-        "text_content": "Newsletter body as plain text",
+        "draft_id": uuid::Uuid::new_v4(),
         "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
     let response = app.post_newsletters(&newsletter_request_body).await;
@@ -50,10 +52,9 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     app.test_user.login(&app).await;
 
     // Act - Publish the newsletter
+    let draft_id = create_sample_draft(&app).await;
     let newsletter_request_body = serde_json::json!({
-        "title": "Newsletter title",
-        "html_content": "<p>Newsletter body as HTML</p>",
-        "text_content": "Newsletter body as plain text",
+        "draft_id": draft_id,
         "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
     let response = app.post_newsletters(&newsletter_request_body).await;
@@ -63,6 +64,8 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let response = app.get_send_newsletter().await;
     let html_page = response.text().await.unwrap();
     assert!(html_page.contains(r#"<p><i>Newsletter "Newsletter title" has been published.</i></p>"#));
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
 }
 
 #[actix_rt::test]
@@ -72,7 +75,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     create_confirmed_subscriber(&app).await;
 
     Mock::given(any())
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(AcceptEveryEmail)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -81,10 +84,9 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     app.test_user.login(&app).await;
 
     // Act - Publish the newsletter
+    let draft_id = create_sample_draft(&app).await;
     let newsletter_request_body = serde_json::json!({
-        "title": "Newsletter title",
-        "html_content": "<p>Newsletter body as HTML</p>",
-        "text_content": "Newsletter body as plain text",
+        "draft_id": draft_id,
         "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
     let response = app.post_newsletters(&newsletter_request_body).await;
@@ -93,6 +95,96 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     // Act - Follow the redirect
     let html_page = app.get_send_newsletter_html().await;
     assert!(html_page.contains(r#"<p><i>Newsletter "Newsletter title" has been published.</i></p>"#));
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[actix_rt::test]
+async fn newsletter_delivery_happens_outside_of_the_publishing_request() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act - Publish the newsletter
+    let delivery_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .named("No delivery while publishing")
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let draft_id = create_sample_draft(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "draft_id": draft_id,
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    drop(delivery_guard);
+
+    // Assert - The issue is stored with one delivery task per confirmed subscriber
+    let issue = sqlx::query!("SELECT newsletter_issue_id, title FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the stored newsletter issue.");
+    assert_eq!(issue.title, "Newsletter title");
+    let queued = sqlx::query!(
+        "SELECT subscriber_email FROM issue_delivery_queue WHERE newsletter_issue_id = $1",
+        issue.newsletter_issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch the delivery queue.");
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, "ursula_le_guin@gmail.com");
+
+    // Act - Drain the queue
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - The queue is empty once the worker is done
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch the delivery queue.");
+    assert!(queued.is_empty());
+}
+
+#[actix_rt::test]
+async fn newsletters_are_delivered_to_many_subscribers_in_a_single_request() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..3 {
+        insert_confirmed_subscriber(&app, &format!("reader-{}@example.com", i), "reader").await;
+    }
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let batch: Vec<serde_json::Value> = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(batch.len(), 3);
+    let statuses = sqlx::query!("SELECT status FROM issue_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses.len(), 3);
+    assert!(statuses.iter().all(|d| d.status == "sent"));
 }
 
 #[actix_rt::test]
@@ -102,24 +194,22 @@ async fn newsletters_returns_400_for_invalid_data() {
     let test_cases = vec![
         (
             serde_json::json!({
-                "text_content": "Newsletter body as plain text",
-                "html_content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": uuid::Uuid::new_v4().to_string(),
             }),
-            "missing title",
+            "missing draft_id",
         ),
         (
             serde_json::json!({
-                "title": "Newsletter!",
-                "html_content": "<p>Newsletter body as HTML</p>"
+                "draft_id": uuid::Uuid::new_v4(),
             }),
-            "missing text_content",
+            "missing idempotency_key",
         ),
         (
             serde_json::json!({
-                "title": "Newsletter!",
-                "text_content": "Newsletter body as plain text",
+                "draft_id": "not-a-uuid",
+                "idempotency_key": uuid::Uuid::new_v4().to_string(),
             }),
-            "missing html_content",
+            "malformed draft_id",
         ),
     ];
 
@@ -146,17 +236,16 @@ async fn newsletter_creation_is_idempotent() {
     app.test_user.login(&app).await;
 
     Mock::given(any())
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(AcceptEveryEmail)
         .expect(1)
         .mount(&app.email_server)
         .await;
 
     // Act - Submit newsletter form
+    let draft_id = create_sample_draft(&app).await;
     let newsletter_request_body = serde_json::json!({
-        "title": "Newsletter title",
-        "html_content": "<p>Newsletter body as HTML</p>",
-        "text_content": "Newsletter body as plain text",
-        "idempotency_key": uuid::Uuid::new_v4().to_string()
+        "draft_id": draft_id,
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
     });
     let response = app.post_newsletters(&newsletter_request_body).await;
     assert_is_redirect_to(&response, "/admin/newsletters");
@@ -173,39 +262,159 @@ async fn newsletter_creation_is_idempotent() {
     let html_page = app.get_send_newsletter_html().await;
     assert!(html_page.contains(r#"<p><i>Newsletter "Newsletter title" has been published.</i></p>"#));
 
+    app.dispatch_all_pending_emails().await;
     // Mock verifies we have only sent the email once when it is dropped
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+#[actix_rt::test]
+async fn concurrent_form_submission_is_handled_gracefully() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
 
-    let _mock_guard = Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
+        .respond_with(AcceptEveryEmail)
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
         .await;
-    app.post_subscriptions(body.into())
+
+    // Act - Submit two newsletter forms concurrently
+    let draft_id = create_sample_draft(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "draft_id": draft_id,
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response1 = app.post_newsletters(&newsletter_request_body);
+    let response2 = app.post_newsletters(&newsletter_request_body);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    // Assert - Both requests get the response of the one that went through
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.headers().get("Location"),
+        response2.headers().get("Location")
+    );
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
         .await
-        .error_for_status()
-        .unwrap();
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies we have only sent the email once when it is dropped
+}
+
+#[actix_rt::test]
+async fn newsletter_bodies_are_personalised_for_each_recipient() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let draft_id = app
+        .create_draft(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": r#"<p>Hi {{ subscriber.name }}</p><a href="{{ unsubscribe_url }}">Unsubscribe</a>"#,
+            "text_content": "Hi {{subscriber.name}} <{{ subscriber.email }}>, read it online: {{ archive_url }}",
+        }))
+        .await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "draft_id": draft_id,
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
 
-    let email_request = &app
+    // Assert
+    let email_request = app
         .email_server
         .received_requests()
         .await
         .unwrap()
         .pop()
         .unwrap();
-    app.get_confirmation_links(email_request)
+    let batch: Vec<serde_json::Value> = serde_json::from_slice(&email_request.body).unwrap();
+    let body = &batch[0];
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<p>Hi le guin</p>"));
+    assert!(html_body.contains(&format!(
+        r#"<a href="{}/subscriptions/unsubscribe?subscription_token="#,
+        app.address
+    )));
+    assert_eq!(
+        body["TextBody"].as_str().unwrap(),
+        format!(
+            "Hi le guin <ursula_le_guin@gmail.com>, read it online: {}/archive/newsletter-title",
+            app.address
+        )
+    );
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_link.html)
+#[actix_rt::test]
+async fn newsletters_with_invalid_templates_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let test_cases = vec![
+        (
+            "<p>Hi {{ subscriber.age }}</p>",
+            "Hi",
+            "HTML content: Unknown variable `{{ subscriber.age }}` on line 1.",
+        ),
+        (
+            "<p>Hi</p>",
+            "Hi\n{{ subscriber.name",
+            "Text content: A `{{` on line 2 is never closed with `}}`.",
+        ),
+    ];
+
+    for (html_content, text_content, error_message) in test_cases {
+        // Act
+        let draft_id = app
+            .create_draft(&serde_json::json!({
+                "title": "Newsletter title",
+                "html_content": html_content,
+                "text_content": text_content,
+            }))
+            .await;
+        let response = app
+            .post_newsletters(&serde_json::json!({
+                "draft_id": draft_id,
+                "idempotency_key": uuid::Uuid::new_v4().to_string(),
+            }))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, &format!("/admin/newsletters/drafts/{}", draft_id));
+        let html_page = app.get_draft_html(draft_id).await;
+        assert!(html_page.contains(&format!(
+            "<p><i>The newsletter has not been published. {}</i></p>",
+            error_message
+        )));
+    }
+    app.dispatch_all_pending_emails().await;
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
         .await
         .unwrap()
-        .error_for_status()
-        .unwrap();
+        .count;
+    assert_eq!(n_issues, 0);
 }
@@ -0,0 +1,93 @@
+use crate::helpers::{
+    create_confirmed_subscriber, create_sample_draft, publish_and_deliver, spawn_app,
+};
+
+/// The URL of the tracking pixel in `html`, relative to the application.
+fn pixel_path(html: &str) -> String {
+    let start = html.find("/t/o/").expect("No tracking pixel found.");
+    let end = start + html[start..].find('"').unwrap();
+    html[start..end].to_string()
+}
+
+#[actix_rt::test]
+async fn opens_are_tracked_when_the_issue_asks_for_it() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let (issue_id, html_body) =
+        publish_and_deliver(&app, create_sample_draft(&app).await, &["track_opens"]).await;
+    let pixel_path = pixel_path(&html_body);
+
+    // Act
+    for _ in 0..2 {
+        let response = reqwest::get(&format!("{}{}", app.address, pixel_path))
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["Content-Type"], "image/gif");
+    }
+
+    // Assert
+    let delivery = sqlx::query!("SELECT first_opened_at, n_opens FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(delivery.first_opened_at.is_some());
+    assert_eq!(delivery.n_opens, 2);
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+    assert!(html_page.contains("<p>Opened by 1 of 1 recipients (100.0%), 2 opens in total.</p>"));
+}
+
+#[actix_rt::test]
+async fn tracking_urls_do_not_reveal_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let (_, html_body) =
+        publish_and_deliver(&app, create_sample_draft(&app).await, &["track_opens"]).await;
+
+    // Assert
+    let pixel_path = pixel_path(&html_body);
+    let subscriber = sqlx::query!("SELECT id, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(!pixel_path.contains(&subscriber.id.to_string()));
+    assert!(!pixel_path.contains(&subscriber.email));
+}
+
+#[actix_rt::test]
+async fn opens_are_not_tracked_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let (issue_id, html_body) =
+        publish_and_deliver(&app, create_sample_draft(&app).await, &[]).await;
+
+    // Assert
+    assert!(!html_body.contains("/t/o/"));
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+    assert!(html_page.contains("<p>Opens are not tracked for this issue.</p>"));
+}
+
+#[actix_rt::test]
+async fn unknown_tracking_tokens_still_get_a_pixel() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(&format!("{}/t/o/not-a-token.gif", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+}
@@ -0,0 +1,163 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_sample_draft, spawn_app,
+    AcceptEveryEmail, TestApp,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn schedule_newsletter(app: &TestApp) -> Uuid {
+    let publish_at = Utc::now() + Duration::days(3);
+    let draft_id = create_sample_draft(app).await;
+    let newsletter_request_body = serde_json::json!({
+        "draft_id": draft_id,
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "publish_at": publish_at.format("%Y-%m-%dT%H:%M").to_string(),
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_send_newsletter_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<p><i>Newsletter "Newsletter title" has been scheduled for {}.</i></p>"#,
+        publish_at.format("%Y-%m-%d %H:%M UTC")
+    )));
+
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn make_scheduled_issues_due(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET published_at = now() - interval '1 minute' \
+        WHERE status = 'scheduled'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_see_scheduled_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_scheduled_newsletters().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_rt::test]
+async fn scheduled_newsletters_are_only_delivered_once_they_are_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act - Schedule the newsletter
+    let guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .named("No delivery before the publishing time")
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    schedule_newsletter(&app).await;
+    app.release_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+    drop(guard);
+
+    // Act - Time passes
+    Mock::given(any())
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    make_scheduled_issues_due(&app).await;
+    app.release_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "published");
+    // Mock verifies on Drop that the newsletter has been delivered
+}
+
+#[actix_rt::test]
+async fn scheduled_newsletters_are_listed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    schedule_newsletter(&app).await;
+
+    // Act
+    let html_page = app.get_scheduled_newsletters_html().await;
+
+    // Assert
+    assert!(html_page.contains("<td>Newsletter title</td>"));
+}
+
+#[actix_rt::test]
+async fn scheduled_newsletters_can_be_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = schedule_newsletter(&app).await;
+
+    // Act
+    let response = app
+        .post_reschedule_newsletter(
+            issue_id,
+            &serde_json::json!({ "publish_at": "2099-01-05T08:00" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/scheduled");
+
+    // Assert
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been rescheduled for 2099-01-05 08:00 UTC.</i></p>"
+    ));
+    assert!(html_page.contains("<td>2099-01-05 08:00 UTC</td>"));
+}
+
+#[actix_rt::test]
+async fn cancelled_newsletters_are_never_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = schedule_newsletter(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Cancel the issue
+    let response = app.post_cancel_newsletter(issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters/scheduled");
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been cancelled.</i></p>"));
+    assert!(html_page.contains("There are no scheduled newsletters."));
+
+    // Act - The publishing time comes
+    make_scheduled_issues_due(&app).await;
+    app.release_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act - Cancelling a second time is a no-op
+    app.post_cancel_newsletter(issue_id).await;
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue is no longer scheduled.</i></p>"));
+    // Mock verifies on Drop that the newsletter has not been delivered
+}
@@ -0,0 +1,192 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_sample_draft, insert_confirmed_subscriber, spawn_app,
+    AcceptEveryEmail, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+async fn create_confirmed_subscribers(app: &TestApp, n: usize) {
+    for i in 0..n {
+        insert_confirmed_subscriber(app, &format!("reader-{}@example.com", i), "reader").await;
+    }
+}
+
+/// Publish a sample draft testing two subject lines on 40% of the list.
+async fn publish_subject_test(app: &TestApp, idempotency_key: &str) -> reqwest::Response {
+    let draft_id = create_sample_draft(app).await;
+    app.post_newsletters(&serde_json::json!({
+        "draft_id": draft_id,
+        "idempotency_key": idempotency_key,
+        "subject_variants": "Variant A\r\nVariant B",
+        "test_sample_percent": "40",
+        "test_duration_hours": "4",
+    }))
+    .await
+}
+
+/// The subject of every email sent so far, sorted.
+async fn sent_subjects(app: &TestApp) -> Vec<String> {
+    let mut subjects = Vec::new();
+    for request in app.email_server.received_requests().await.unwrap() {
+        let batch: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        for message in batch {
+            subjects.push(message["Subject"].as_str().unwrap().to_string());
+        }
+    }
+    subjects.sort();
+    subjects
+}
+
+#[actix_rt::test]
+async fn the_sample_is_split_between_the_subject_lines() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 10).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = publish_subject_test(&app, &Uuid::new_v4().to_string()).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    app.complete_due_subject_tests().await;
+
+    // Assert
+    assert_eq!(
+        sent_subjects(&app).await,
+        vec!["Variant A", "Variant A", "Variant B", "Variant B"]
+    );
+    let issue = sqlx::query!("SELECT track_opens FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue.track_opens);
+}
+
+#[actix_rt::test]
+async fn the_winning_subject_line_goes_to_everybody_else_once_the_test_is_over() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 10).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .mount(&app.email_server)
+        .await;
+    publish_subject_test(&app, &Uuid::new_v4().to_string()).await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("UPDATE issue_deliveries SET first_opened_at = now() WHERE variant_id = 2")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    sqlx::query!("UPDATE subject_tests SET test_ends_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.complete_due_subject_tests().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let subjects = sent_subjects(&app).await;
+    assert_eq!(subjects.len(), 10);
+    assert_eq!(subjects.iter().filter(|s| *s == "Variant A").count(), 2);
+    assert_eq!(subjects.iter().filter(|s| *s == "Variant B").count(), 8);
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let html_page = app.get_newsletter_issue_report_html(issue_id, "").await;
+    let winner_row = "<tr><td>Variant B (winner)</td><td>8</td><td>2</td><td>25.0%</td></tr>";
+    assert!(html_page.contains(winner_row));
+}
+
+#[actix_rt::test]
+async fn submitting_a_subject_test_twice_starts_a_single_test() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 10).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_sample_draft(&app).await;
+    let body = serde_json::json!({
+        "draft_id": draft_id,
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "subject_variants": "Variant A\r\nVariant B",
+        "test_sample_percent": "40",
+        "test_duration_hours": "4",
+    });
+
+    // Act
+    for _ in 0..2 {
+        let response = app.post_newsletters(&body).await;
+        assert_is_redirect_to(&response, "/admin/newsletters");
+    }
+
+    // Assert
+    let n_tests = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM subject_tests"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tests, 1);
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 4);
+}
+
+#[actix_rt::test]
+async fn invalid_subject_tests_are_not_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        ("Only one", "20", "4", "Enter at least two subject lines to test."),
+        (
+            "A\nB",
+            "100",
+            "4",
+            "The test sample must be between 1% and 99% of the recipients.",
+        ),
+        ("A\nB", "20", "0", "The test must last at least one hour."),
+    ];
+
+    for (subject_variants, sample_percent, duration_hours, error_message) in test_cases {
+        let draft_id = create_sample_draft(&app).await;
+
+        // Act
+        let response = app
+            .post_newsletters(&serde_json::json!({
+                "draft_id": draft_id,
+                "idempotency_key": Uuid::new_v4().to_string(),
+                "subject_variants": subject_variants,
+                "test_sample_percent": sample_percent,
+                "test_duration_hours": duration_hours,
+            }))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, &format!("/admin/newsletters/drafts/{}", draft_id));
+        let html_page = app.get_draft_html(draft_id).await;
+        assert!(html_page.contains(&format!(
+            "<p><i>The newsletter has not been published. {}</i></p>",
+            error_message
+        )));
+    }
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
@@ -129,6 +129,49 @@ async fn following_a_confirmation_link_with_an_unknown_token_returns_unauthorize
     assert_eq!(response.status().as_u16(), 401)
 }
 
+#[actix_rt::test]
+async fn confirmation_links_do_not_revive_addresses_that_bounced() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    sqlx::query!("UPDATE subscriptions SET status = 'bounced'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "bounced");
+    let memberships = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(memberships
+        .iter()
+        .all(|m| m.status == "pending_confirmation"));
+}
+
 async fn assert_subscriber_saved(db_pool: &PgPool) {
     let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
         .fetch_one(db_pool)
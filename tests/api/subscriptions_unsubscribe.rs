@@ -0,0 +1,282 @@
+use crate::helpers::{
+    create_confirmed_subscriber, create_sample_draft, publish_and_deliver, publish_newsletter,
+    spawn_app, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn get_subscription_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token
+}
+
+async fn get_subscription_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+async fn get_membership_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+/// The headers of the first message of the last batch sent, by name.
+async fn get_sent_headers(app: &TestApp) -> Vec<(String, String)> {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let batch: Vec<serde_json::Value> = serde_json::from_slice(&email_request.body).unwrap();
+    batch[0]["Headers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|h| {
+            (
+                h["Name"].as_str().unwrap().to_string(),
+                h["Value"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+/// The URL of the `List-Unsubscribe` header of the last issue sent.
+async fn get_one_click_url(app: &TestApp) -> String {
+    let (_, value) = get_sent_headers(app)
+        .await
+        .into_iter()
+        .find(|(name, _)| name == "List-Unsubscribe")
+        .expect("No List-Unsubscribe header.");
+    value
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_string()
+}
+
+async fn post_one_click(app: &TestApp, url: &str, body: &'static str) -> reqwest::Response {
+    app.api_client
+        .post(url)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[actix_rt::test]
+async fn unsubscribing_without_token_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(&format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_rt::test]
+async fn the_unsubscribe_link_asks_for_a_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscription_token = get_subscription_token(&app).await;
+
+    // Act
+    let response = app.get_unsubscribe(&subscription_token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<form action="/subscriptions/unsubscribe" method="post">"#));
+    assert_eq!(get_subscription_status(&app).await, "confirmed");
+}
+
+#[actix_rt::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscription_token = get_subscription_token(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_unsubscribe(&subscription_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Assert
+    assert_eq!(get_subscription_status(&app).await, "unsubscribed");
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[actix_rt::test]
+async fn unsubscribing_keeps_addresses_that_complained_suppressed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscription_token = get_subscription_token(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'complained'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.post_unsubscribe(&subscription_token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_subscription_status(&app).await, "complained");
+    assert_eq!(get_membership_status(&app).await, "unsubscribed");
+}
+
+#[actix_rt::test]
+async fn subscribers_who_unsubscribed_can_subscribe_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscription_token = get_subscription_token(&app).await;
+    let response = app.post_unsubscribe(&subscription_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    create_confirmed_subscriber(&app).await;
+
+    // Assert
+    assert_eq!(get_subscription_status(&app).await, "confirmed");
+    assert_eq!(get_membership_status(&app).await, "confirmed");
+    app.test_user.login(&app).await;
+    let (_, html_body) = publish_and_deliver(&app, create_sample_draft(&app).await, &[]).await;
+    assert!(html_body.contains("Newsletter body as HTML"));
+}
+
+#[actix_rt::test]
+async fn unknown_tokens_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_unsubscribe("not-a-real-token").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_rt::test]
+async fn issues_carry_one_click_unsubscribe_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_and_deliver(&app, create_sample_draft(&app).await, &[]).await;
+
+    // Assert
+    let headers = get_sent_headers(&app).await;
+    assert!(headers.contains(&(
+        "List-Unsubscribe-Post".to_string(),
+        "List-Unsubscribe=One-Click".to_string()
+    )));
+    let url = get_one_click_url(&app).await;
+    assert!(url.starts_with(&format!(
+        "{}/subscriptions/unsubscribe/one-click?",
+        app.address
+    )));
+}
+
+#[actix_rt::test]
+async fn one_click_unsubscribe_takes_the_subscriber_off_the_list() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let (issue_id, _) = publish_and_deliver(&app, create_sample_draft(&app).await, &[]).await;
+    let url = get_one_click_url(&app).await;
+
+    // Act
+    let response = post_one_click(&app, &url, "List-Unsubscribe=One-Click").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_membership_status(&app).await, "unsubscribed");
+    let unsubscribes = sqlx::query!("SELECT newsletter_issue_id FROM issue_unsubscribes")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(unsubscribes.len(), 1);
+    assert_eq!(unsubscribes[0].newsletter_issue_id, issue_id);
+}
+
+#[actix_rt::test]
+async fn one_click_unsubscribe_rejects_tampered_urls() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_and_deliver(&app, create_sample_draft(&app).await, &[]).await;
+    let url = get_one_click_url(&app).await;
+    let list_id = sqlx::query!("SELECT list_id FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+    let tampered_url = url.replace(&list_id.to_string(), &Uuid::new_v4().to_string());
+
+    // Act
+    let response = post_one_click(&app, &tampered_url, "List-Unsubscribe=One-Click").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(get_membership_status(&app).await, "confirmed");
+}
+
+#[actix_rt::test]
+async fn one_click_unsubscribe_accepts_multipart_bodies() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_and_deliver(&app, create_sample_draft(&app).await, &[]).await;
+    let url = get_one_click_url(&app).await;
+    let body = "--boundary\r\n\
+        Content-Disposition: form-data; name=\"List-Unsubscribe\"\r\n\
+        \r\n\
+        One-Click\r\n\
+        --boundary--\r\n";
+
+    // Act
+    let response = app
+        .api_client
+        .post(&url)
+        .header("Content-Type", "multipart/form-data; boundary=boundary")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_membership_status(&app).await, "unsubscribed");
+}
@@ -0,0 +1,221 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_sample_draft, insert_confirmed_subscriber, spawn_app,
+    AcceptEveryEmail, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn get_subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn get_tags(app: &TestApp, email: &str) -> Vec<String> {
+    sqlx::query!(
+        r#"
+        SELECT t.tag
+        FROM subscriber_tags t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE s.email = $1
+        ORDER BY t.tag
+        "#,
+        email
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.tag)
+    .collect()
+}
+
+/// Three confirmed subscribers: `alice` tagged `rust`, `bob` tagged `rust`
+/// and `beta`, `carol` untagged.
+async fn create_tagged_subscribers(app: &TestApp) {
+    for (email, tags) in [
+        ("alice@example.com", "rust"),
+        ("bob@example.com", "rust, beta"),
+        ("carol@example.com", ""),
+    ] {
+        insert_confirmed_subscriber(app, email, "reader").await;
+        let subscriber_id = get_subscriber_id(app, email).await;
+        let response = app
+            .post_subscriber_tags(subscriber_id, &serde_json::json!({ "tags": tags }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/subscribers");
+    }
+}
+
+#[actix_rt::test]
+async fn you_must_be_logged_in_to_tag_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "alice@example.com", "reader").await;
+    let subscriber_id = get_subscriber_id(&app, "alice@example.com").await;
+
+    // Act
+    let response = app
+        .post_subscriber_tags(subscriber_id, &serde_json::json!({ "tags": "rust" }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(get_tags(&app, "alice@example.com").await.is_empty());
+}
+
+#[actix_rt::test]
+async fn subscribers_are_tagged_by_the_subscription_form() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&tags=Rust%2C%20Beta%2C%20rust";
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        get_tags(&app, "ursula_le_guin@gmail.com").await,
+        vec!["beta".to_string(), "rust".to_string()]
+    );
+}
+
+#[actix_rt::test]
+async fn invalid_tags_are_rejected_by_the_subscription_form() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&tags=%3Cscript%3E";
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_rt::test]
+async fn admins_can_replace_the_tags_of_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_confirmed_subscriber(&app, "alice@example.com", "reader").await;
+    let subscriber_id = get_subscriber_id(&app, "alice@example.com").await;
+    app.post_subscriber_tags(subscriber_id, &serde_json::json!({ "tags": "rust, beta" }))
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriber_tags(subscriber_id, &serde_json::json!({ "tags": "Beta, go" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    // Assert
+    let html_page = app.get_subscribers_html("email=alice").await;
+    assert!(html_page.contains("<p><i>The tags of alice@example.com have been updated.</i></p>"));
+    assert!(html_page.contains(r#"value="beta, go""#));
+    assert_eq!(
+        get_tags(&app, "alice@example.com").await,
+        vec!["beta".to_string(), "go".to_string()]
+    );
+}
+
+#[actix_rt::test]
+async fn the_subscriber_email_filter_matches_wildcard_characters_literally() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_confirmed_subscriber(&app, "a_b@example.com", "reader").await;
+    insert_confirmed_subscriber(&app, "axb@example.com", "reader").await;
+
+    // Act
+    let html_page = app.get_subscribers_html("email=A_B%40").await;
+
+    // Assert
+    assert!(html_page.contains("<td>a_b@example.com</td>"));
+    assert!(!html_page.contains("<td>axb@example.com</td>"));
+}
+
+#[actix_rt::test]
+async fn the_recipient_count_follows_the_tag_filters() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_tagged_subscribers(&app).await;
+
+    for (query, expected_count) in [
+        ("", 3),
+        ("include_tags=rust", 2),
+        ("include_tags=rust&exclude_tags=beta", 1),
+        ("exclude_tags=rust", 1),
+        ("include_tags=go", 0),
+    ] {
+        // Act
+        let response = app.get_recipient_count(query).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200, "Query: {}", query);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["count"], expected_count, "Query: {}", query);
+    }
+}
+
+#[actix_rt::test]
+async fn the_recipient_count_rejects_invalid_tags() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_recipient_count("include_tags=%3Cscript%3E").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_rt::test]
+async fn issues_only_go_to_subscribers_matching_the_tag_filters() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_tagged_subscribers(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(AcceptEveryEmail)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let draft_id = create_sample_draft(&app).await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "draft_id": draft_id,
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "include_tags": "rust",
+            "exclude_tags": "beta",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let batch: Vec<serde_json::Value> = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(batch.len(), 1);
+    assert_eq!(batch[0]["To"], "alice@example.com");
+}